@@ -1,5 +1 @@
 pub mod s0;
-
-struct Symbol {
-    name: String,
-}
@@ -1,14 +1,361 @@
-use crate::context::Context;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 
+use crate::{context::Context, util::Symbol};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ident {
+    pub name: &'static Symbol,
+    pub context: Context,
+}
+
+#[derive(Debug)]
 pub enum Type {
+    /// A parameter of the enclosing `data` declaration
+    Var(Ident),
+    /// A type constructor applied to arguments, e.g. `Int` or `List a`
+    Named {
+        name: Ident,
+        args: Vec<Type>,
+        context: Context,
+    },
 }
 
+#[derive(Debug)]
 pub struct DataCons {
+    pub name: Ident,
+    pub fields: Vec<Type>,
+    pub context: Context,
 }
 
+#[derive(Debug)]
 pub struct DataDef {
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    pub cons: Vec<DataCons>,
+    pub context: Context,
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    Wildcard(Context),
+    Bind(Ident),
+    Cons {
+        name: Ident,
+        args: Vec<Pattern>,
+        context: Context,
+    },
+    IntLit {
+        value: i64,
+        context: Context,
+    },
+    BoolLit {
+        value: bool,
+        context: Context,
+    },
+    StrLit {
+        value: Rc<str>,
+        context: Context,
+    },
+    CharLit {
+        value: char,
+        context: Context,
+    },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    NotEq,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Tree,
+    pub context: Context,
+}
+
+#[derive(Debug)]
 pub enum Tree {
-    // Data {
-}
\ No newline at end of file
+    /// Sequence of items, evaluating to the last one
+    Block {
+        items: Vec<Tree>,
+        context: Context,
+    },
+    Data(DataDef),
+    Let {
+        name: Ident,
+        value: Box<Tree>,
+        context: Context,
+    },
+    Fn {
+        params: Vec<Ident>,
+        /// Shared with the closures created from it
+        body: Rc<Tree>,
+        context: Context,
+    },
+    Match {
+        scrutinee: Box<Tree>,
+        arms: Vec<MatchArm>,
+        context: Context,
+    },
+    If {
+        cond: Box<Tree>,
+        then: Box<Tree>,
+        els: Box<Tree>,
+        context: Context,
+    },
+    Call {
+        func: Box<Tree>,
+        args: Vec<Tree>,
+        context: Context,
+    },
+    Binary {
+        op: BinOp,
+        lhs: Box<Tree>,
+        rhs: Box<Tree>,
+        context: Context,
+    },
+    Unary {
+        op: UnOp,
+        operand: Box<Tree>,
+        context: Context,
+    },
+    Var(Ident),
+    IntLit {
+        value: i64,
+        context: Context,
+    },
+    BoolLit {
+        value: bool,
+        context: Context,
+    },
+    StrLit {
+        value: Rc<str>,
+        context: Context,
+    },
+    CharLit {
+        value: char,
+        context: Context,
+    },
+    /// Where a syntax error was, in a tree parsed despite it
+    Error(Context),
+}
+
+impl Type {
+    pub fn context(&self) -> Context {
+        match self {
+            Type::Var(ident) => ident.context,
+            Type::Named { context, .. } => *context,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn context(&self) -> Context {
+        use Pattern::*;
+        match self {
+            Wildcard(context) => *context,
+            Bind(ident) => ident.context,
+            Cons { context, .. }
+            | IntLit { context, .. }
+            | BoolLit { context, .. }
+            | StrLit { context, .. }
+            | CharLit { context, .. } => *context,
+        }
+    }
+}
+
+impl Tree {
+    pub fn context(&self) -> Context {
+        use Tree::*;
+        match self {
+            Data(def) => def.context,
+            Var(ident) => ident.context,
+            Error(context) => *context,
+            Block { context, .. }
+            | Let { context, .. }
+            | Fn { context, .. }
+            | Match { context, .. }
+            | If { context, .. }
+            | Call { context, .. }
+            | Binary { context, .. }
+            | Unary { context, .. }
+            | IntLit { context, .. }
+            | BoolLit { context, .. }
+            | StrLit { context, .. }
+            | CharLit { context, .. } => *context,
+        }
+    }
+}
+
+// s-expression rendering, mostly for debugging the parser
+
+fn write_list<T: Display>(f: &mut Formatter, items: &[T]) -> fmt::Result {
+    for item in items {
+        write!(f, " {}", item)?;
+    }
+    Ok(())
+}
+
+impl Display for Ident {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name.name)
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use BinOp::*;
+        let s = match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            Eq => "==",
+            NotEq => "!=",
+            And => "&&",
+            Or => "||",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for UnOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnOp::Neg => write!(f, "-"),
+            UnOp::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Var(name) => write!(f, "{}", name),
+            Type::Named { name, args, .. } if args.is_empty() => write!(f, "{}", name),
+            Type::Named { name, args, .. } => {
+                write!(f, "({}", name)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Display for DataCons {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "({}", self.name)?;
+        write_list(f, &self.fields)?;
+        write!(f, ")")
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Pattern::*;
+        match self {
+            Wildcard(_) => write!(f, "_"),
+            Bind(name) => write!(f, "{}", name),
+            Cons { name, args, .. } if args.is_empty() => write!(f, "{}", name),
+            Cons { name, args, .. } => {
+                write!(f, "({}", name)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            IntLit { value, .. } => write!(f, "{}", value),
+            BoolLit { value, .. } => write!(f, "{}", value),
+            StrLit { value, .. } => write!(f, "{:?}", value),
+            CharLit { value, .. } => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl Display for MatchArm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "({} {})", self.pattern, self.body)
+    }
+}
+
+impl Display for Tree {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Tree::*;
+        match self {
+            Block { items, .. } => {
+                write!(f, "(block")?;
+                write_list(f, items)?;
+                write!(f, ")")
+            }
+            Data(def) => {
+                write!(f, "(data ")?;
+                if def.params.is_empty() {
+                    write!(f, "{}", def.name)?;
+                } else {
+                    write!(f, "({}", def.name)?;
+                    write_list(f, &def.params)?;
+                    write!(f, ")")?;
+                }
+                write_list(f, &def.cons)?;
+                write!(f, ")")
+            }
+            Let { name, value, .. } => write!(f, "(let {} {})", name, value),
+            Fn { params, body, .. } => {
+                write!(f, "(fn (")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") {})", body)
+            }
+            Match {
+                scrutinee, arms, ..
+            } => {
+                write!(f, "(match {}", scrutinee)?;
+                write_list(f, arms)?;
+                write!(f, ")")
+            }
+            If {
+                cond, then, els, ..
+            } => write!(f, "(if {} {} {})", cond, then, els),
+            Call { func, args, .. } => {
+                write!(f, "({}", func)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Binary { op, lhs, rhs, .. } => write!(f, "({} {} {})", op, lhs, rhs),
+            Unary { op, operand, .. } => write!(f, "({} {})", op, operand),
+            Var(name) => write!(f, "{}", name),
+            IntLit { value, .. } => write!(f, "{}", value),
+            BoolLit { value, .. } => write!(f, "{}", value),
+            StrLit { value, .. } => write!(f, "{:?}", value),
+            CharLit { value, .. } => write!(f, "{:?}", value),
+            Error(_) => write!(f, "(error)"),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! A lossless concrete syntax tree, which reproduces its source byte for byte
+//!
+//! The tree is built from the tokens of [`Lexer::lex_with_trivia`] and the
+//! spans of the AST parsed from them: each node holds the tokens within its
+//! span that none of its children do, and every token holds the whitespace
+//! and comments around it.
+//!
+//! [`Lexer::lex_with_trivia`]: crate::lexer::Lexer::lex_with_trivia
+
+use std::fmt::{self, Display, Formatter};
+use std::iter::Peekable;
+
+use crate::{
+    ast::s0::{DataCons, MatchArm, Pattern, Tree, Type},
+    context::Context,
+    lexer::{Token, TokenKind},
+    source,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub context: Context,
+}
+
+/// A token with its trivia: trailing trivia runs to the end of the token's
+/// line, and leading trivia is everything else since the previous token
+#[derive(Debug)]
+pub struct CstToken {
+    pub token: TokenKind,
+    pub context: Context,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+    Block,
+    Data,
+    Constructor,
+    Let,
+    Fn,
+    Match,
+    Arm,
+    If,
+    Call,
+    Binary,
+    Unary,
+    Name,
+    Literal,
+    WildcardPattern,
+    BindPattern,
+    ConstructorPattern,
+    LiteralPattern,
+    TypeVar,
+    NamedType,
+    /// Tokens skipped over after a syntax error
+    Error,
+}
+
+#[derive(Debug)]
+pub enum Element {
+    Node(Node),
+    Token(CstToken),
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+/// Builds the tree of a program from all of its tokens, trivia included,
+/// and its AST
+pub fn build(tokens: Vec<Token>, tree: &Tree) -> Node {
+    let mut tokens = attach_trivia(tokens).into_iter().peekable();
+    let mut root = Node {
+        kind: NodeKind::Program,
+        children: Vec::new(),
+    };
+
+    // a lone expression is the program, rather than its only item
+    let items = match tree {
+        Tree::Block { items, .. } => items.iter().map(Syntax::Tree).collect(),
+        tree => vec![Syntax::Tree(tree)],
+    };
+    for item in items {
+        root.children.push(Element::Node(node(item, &mut tokens)));
+    }
+    root.children.extend(tokens.map(Element::Token));
+    root
+}
+
+/// Groups trivia tokens, and gives them to the tokens they surround
+fn attach_trivia(tokens: Vec<Token>) -> Vec<CstToken> {
+    let mut out: Vec<CstToken> = Vec::new();
+    let mut pending = Vec::new();
+    // whether trivia still trails the last token, before any newline
+    let mut trailing = false;
+
+    for token in tokens {
+        let kind = match token.token {
+            TokenKind::Comment => TriviaKind::Comment,
+            TokenKind::None if source::get(token.context.file).src[token.context.start..].starts_with('\n') => {
+                TriviaKind::Newline
+            }
+            TokenKind::None => TriviaKind::Whitespace,
+            _ => {
+                out.push(CstToken {
+                    token: token.token,
+                    context: token.context,
+                    leading: std::mem::take(&mut pending),
+                    trailing: Vec::new(),
+                });
+                trailing = true;
+                continue;
+            }
+        };
+
+        if kind == TriviaKind::Newline {
+            trailing = false;
+        }
+        let trivia = match trailing {
+            true => &mut out.last_mut().unwrap().trailing,
+            false => &mut pending,
+        };
+
+        // whitespace is lexed a character at a time
+        match trivia.last_mut() {
+            Some(last) if kind == TriviaKind::Whitespace && last.kind == TriviaKind::Whitespace => {
+                last.context = last.context.to(&token.context);
+            }
+            _ => trivia.push(Trivia {
+                kind,
+                context: token.context,
+            }),
+        }
+    }
+    out
+}
+
+/// The node for a piece of the AST, taking the tokens up to its end
+fn node(syntax: Syntax, tokens: &mut Peekable<impl Iterator<Item = CstToken>>) -> Node {
+    let context = syntax.context();
+    let mut children = Vec::new();
+
+    for child in syntax.children() {
+        let start = child.context().start;
+        while let Some(token) = tokens.next_if(|token| token.context.start < start) {
+            children.push(Element::Token(token));
+        }
+        children.push(Element::Node(node(child, tokens)));
+    }
+
+    let end = context.start + context.len;
+    while let Some(token) = tokens.next_if(|token| token.token != TokenKind::Eof && token.context.start < end) {
+        children.push(Element::Token(token));
+    }
+
+    Node {
+        kind: syntax.kind(),
+        children,
+    }
+}
+
+/// Any piece of the AST with a span of its own
+#[derive(Clone, Copy)]
+enum Syntax<'a> {
+    Tree(&'a Tree),
+    Constructor(&'a DataCons),
+    Arm(&'a MatchArm),
+    Pattern(&'a Pattern),
+    Type(&'a Type),
+}
+
+impl<'a> Syntax<'a> {
+    fn context(&self) -> Context {
+        match self {
+            Syntax::Tree(Tree::Data(def)) => def.context,
+            Syntax::Tree(tree) => tree.context(),
+            Syntax::Constructor(cons) => cons.context,
+            Syntax::Arm(arm) => arm.context,
+            Syntax::Pattern(pattern) => pattern.context(),
+            Syntax::Type(ty) => ty.context(),
+        }
+    }
+
+    fn kind(&self) -> NodeKind {
+        match self {
+            Syntax::Tree(tree) => match tree {
+                Tree::Block { .. } => NodeKind::Block,
+                Tree::Data(_) => NodeKind::Data,
+                Tree::Let { .. } => NodeKind::Let,
+                Tree::Fn { .. } => NodeKind::Fn,
+                Tree::Match { .. } => NodeKind::Match,
+                Tree::If { .. } => NodeKind::If,
+                Tree::Call { .. } => NodeKind::Call,
+                Tree::Binary { .. } => NodeKind::Binary,
+                Tree::Unary { .. } => NodeKind::Unary,
+                Tree::Var(_) => NodeKind::Name,
+                Tree::IntLit { .. } | Tree::BoolLit { .. } | Tree::StrLit { .. } | Tree::CharLit { .. } => {
+                    NodeKind::Literal
+                }
+                Tree::Error(_) => NodeKind::Error,
+            },
+            Syntax::Constructor(_) => NodeKind::Constructor,
+            Syntax::Arm(_) => NodeKind::Arm,
+            Syntax::Pattern(pattern) => match pattern {
+                Pattern::Wildcard(_) => NodeKind::WildcardPattern,
+                Pattern::Bind(_) => NodeKind::BindPattern,
+                Pattern::Cons { .. } => NodeKind::ConstructorPattern,
+                _ => NodeKind::LiteralPattern,
+            },
+            Syntax::Type(Type::Var(_)) => NodeKind::TypeVar,
+            Syntax::Type(Type::Named { .. }) => NodeKind::NamedType,
+        }
+    }
+
+    /// The pieces within this one, in source order
+    fn children(&self) -> Vec<Syntax<'a>> {
+        match *self {
+            Syntax::Tree(tree) => match tree {
+                Tree::Block { items, .. } => items.iter().map(Syntax::Tree).collect(),
+                Tree::Data(def) => def.cons.iter().map(Syntax::Constructor).collect(),
+                Tree::Let { value, .. } => vec![Syntax::Tree(value)],
+                Tree::Fn { body, .. } => vec![Syntax::Tree(body)],
+                Tree::Match { scrutinee, arms, .. } => std::iter::once(Syntax::Tree(scrutinee))
+                    .chain(arms.iter().map(Syntax::Arm))
+                    .collect(),
+                Tree::If { cond, then, els, .. } => vec![Syntax::Tree(cond), Syntax::Tree(then), Syntax::Tree(els)],
+                Tree::Call { func, args, .. } => std::iter::once(Syntax::Tree(func))
+                    .chain(args.iter().map(Syntax::Tree))
+                    .collect(),
+                Tree::Binary { lhs, rhs, .. } => vec![Syntax::Tree(lhs), Syntax::Tree(rhs)],
+                Tree::Unary { operand, .. } => vec![Syntax::Tree(operand)],
+                Tree::Var(_)
+                | Tree::IntLit { .. }
+                | Tree::BoolLit { .. }
+                | Tree::StrLit { .. }
+                | Tree::CharLit { .. }
+                | Tree::Error(_) => Vec::new(),
+            },
+            Syntax::Constructor(cons) => cons.fields.iter().map(Syntax::Type).collect(),
+            Syntax::Arm(arm) => vec![Syntax::Pattern(&arm.pattern), Syntax::Tree(&arm.body)],
+            Syntax::Pattern(Pattern::Cons { args, .. }) => args.iter().map(Syntax::Pattern).collect(),
+            Syntax::Pattern(_) => Vec::new(),
+            Syntax::Type(Type::Named { args, .. }) => args.iter().map(Syntax::Type).collect(),
+            Syntax::Type(Type::Var(_)) => Vec::new(),
+        }
+    }
+}
+
+fn text(context: Context) -> String {
+    source::get(context.file).src[context.start..context.start + context.len].to_string()
+}
+
+fn trivia_texts(trivia: &[Trivia]) -> Vec<String> {
+    trivia.iter().map(|trivia| text(trivia.context)).collect()
+}
+
+impl Node {
+    /// Every token in the tree, in source order
+    pub fn tokens(&self) -> Vec<&CstToken> {
+        let mut tokens = Vec::new();
+        let mut work = vec![self.children.iter()];
+        while let Some(children) = work.last_mut() {
+            match children.next() {
+                Some(Element::Token(token)) => tokens.push(token),
+                Some(Element::Node(node)) => work.push(node.children.iter()),
+                None => {
+                    work.pop();
+                }
+            }
+        }
+        tokens
+    }
+
+    /// The tree's structure, a line per node and token
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.dump_into(&mut out, 0);
+        out
+    }
+
+    fn dump_into(&self, out: &mut String, depth: usize) {
+        *out += &format!("{}{:?}\n", "  ".repeat(depth), self.kind);
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.dump_into(out, depth + 1),
+                Element::Token(token) => {
+                    *out += &format!("{}{:?}", "  ".repeat(depth + 1), text(token.context));
+                    if !token.leading.is_empty() {
+                        *out += &format!(" leading {:?}", trivia_texts(&token.leading));
+                    }
+                    if !token.trailing.is_empty() {
+                        *out += &format!(" trailing {:?}", trivia_texts(&token.trailing));
+                    }
+                    out.push('\n');
+                }
+            }
+        }
+    }
+}
+
+/// The exact source text of the tree
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let tokens = self.tokens();
+        let Some(first) = tokens.first() else {
+            return Ok(());
+        };
+        let file = source::get(first.context.file);
+        let text = |context: Context| &file.src[context.start..context.start + context.len];
+
+        for token in tokens {
+            for trivia in &token.leading {
+                write!(f, "{}", text(trivia.context))?;
+            }
+            write!(f, "{}", text(token.context))?;
+            for trivia in &token.trailing {
+                write!(f, "{}", text(trivia.context))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Compiles `match` arms into a decision tree, which tests each part of the
+//! scrutinee at most once on any path
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{ast::s0::*, util::Symbol};
+
+/// Where a value sits inside the scrutinee, as field indices from the top
+pub type Path = Vec<usize>;
+
+/// Every constructor of the type each constructor belongs to, with arities
+pub type Constructors = HashMap<&'static Symbol, Rc<Vec<(&'static Symbol, usize)>>>;
+
+/// Adds the constructors of a `data` declaration, replacing any of the
+/// same names
+pub fn declare(constructors: &mut Constructors, def: &DataDef) {
+    let all = Rc::new(def.cons.iter().map(|cons| (cons.name.name, cons.fields.len())).collect::<Vec<_>>());
+    for cons in &def.cons {
+        constructors.insert(cons.name.name, all.clone());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Case {
+    Cons { name: &'static Symbol, arity: usize },
+    Int(i64),
+    Bool(bool),
+    Str(Rc<str>),
+    Char(char),
+}
+
+impl Case {
+    fn arity(&self) -> usize {
+        match self {
+            Case::Cons { arity, .. } => *arity,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Decision {
+    /// No arm matches
+    Fail,
+    /// Run an arm, binding its variables to the values at their paths
+    Leaf {
+        arm: usize,
+        bindings: Vec<(&'static Symbol, Path)>,
+    },
+    /// Branch on the constructor or literal at `path`
+    Switch {
+        path: Path,
+        cases: Vec<(Case, Decision)>,
+        /// Taken when no case applies; `None` when the cases cover every value
+        default: Option<Box<Decision>>,
+    },
+}
+
+/// `None` stands for a wildcard made up while specializing
+#[derive(Clone)]
+struct Row<'a> {
+    patterns: Vec<Option<&'a Pattern>>,
+    bindings: Vec<(&'static Symbol, Path)>,
+    arm: usize,
+}
+
+/// Compiles the arms of one `match`, whose constructors' types are in
+/// `constructors`
+pub fn compile(arms: &[MatchArm], constructors: &Constructors) -> Decision {
+    let rows = arms
+        .iter()
+        .enumerate()
+        .map(|(arm, a)| Row {
+            patterns: vec![Some(&a.pattern)],
+            bindings: Vec::new(),
+            arm,
+        })
+        .collect();
+    matrix(rows, vec![Vec::new()], constructors)
+}
+
+fn case(pattern: Option<&Pattern>) -> Option<Case> {
+    match pattern? {
+        Pattern::Wildcard(_) | Pattern::Bind(_) => None,
+        Pattern::Cons { name, args, .. } => Some(Case::Cons {
+            name: name.name,
+            arity: args.len(),
+        }),
+        Pattern::IntLit { value, .. } => Some(Case::Int(*value)),
+        Pattern::BoolLit { value, .. } => Some(Case::Bool(*value)),
+        Pattern::StrLit { value, .. } => Some(Case::Str(value.clone())),
+        Pattern::CharLit { value, .. } => Some(Case::Char(*value)),
+    }
+}
+
+/// Removes column `col` from `row`, recording a variable it binds
+fn take<'a>(mut row: Row<'a>, col: usize, path: &Path) -> Row<'a> {
+    if let Some(Pattern::Bind(ident)) = row.patterns.remove(col) {
+        row.bindings.push((ident.name, path.clone()));
+    }
+    row
+}
+
+fn matrix(rows: Vec<Row>, paths: Vec<Path>, constructors: &Constructors) -> Decision {
+    let Some(first) = rows.first() else {
+        return Decision::Fail;
+    };
+
+    // the first row matches if none of its patterns test anything
+    let Some(col) = first.patterns.iter().position(|p| case(*p).is_some()) else {
+        let mut row = rows.into_iter().next().unwrap();
+        for (pattern, path) in row.patterns.iter().zip(&paths) {
+            if let Some(Pattern::Bind(ident)) = pattern {
+                row.bindings.push((ident.name, path.clone()));
+            }
+        }
+        return Decision::Leaf {
+            arm: row.arm,
+            bindings: row.bindings,
+        };
+    };
+
+    let mut cases = Vec::new();
+    for row in &rows {
+        if let Some(case) = case(row.patterns[col]) {
+            if !cases.contains(&case) {
+                cases.push(case);
+            }
+        }
+    }
+
+    let path = paths[col].clone();
+    let mut rest = paths.clone();
+    rest.remove(col);
+
+    let branches = cases
+        .iter()
+        .map(|case| {
+            let mut paths = (0..case.arity())
+                .map(|i| {
+                    let mut field = path.clone();
+                    field.push(i);
+                    field
+                })
+                .collect::<Vec<_>>();
+            paths.extend(rest.iter().cloned());
+
+            let rows = rows
+                .iter()
+                .filter_map(|row| specialize(row, col, case, &path))
+                .collect();
+            (case.clone(), matrix(rows, paths, constructors))
+        })
+        .collect();
+
+    let default = match complete(&cases, constructors) {
+        true => None,
+        false => {
+            let rows = rows
+                .into_iter()
+                .filter(|row| case(row.patterns[col]).is_none())
+                .map(|row| take(row, col, &path))
+                .collect();
+            Some(Box::new(matrix(rows, rest, constructors)))
+        }
+    };
+
+    Decision::Switch {
+        path,
+        cases: branches,
+        default,
+    }
+}
+
+/// Whether `cases` cover every value of their type
+fn complete(cases: &[Case], constructors: &Constructors) -> bool {
+    match cases.first() {
+        Some(Case::Cons { name, .. }) => match constructors.get(name) {
+            Some(all) => all.iter().all(|&(cons, arity)| cases.contains(&Case::Cons { name: cons, arity })),
+            None => false,
+        },
+        Some(Case::Bool(_)) => cases.contains(&Case::Bool(true)) && cases.contains(&Case::Bool(false)),
+        // literals never cover their whole type
+        _ => false,
+    }
+}
+
+/// The row for when the value in column `col` is `target`, with that column
+/// replaced by the case's fields
+fn specialize<'a>(row: &Row<'a>, col: usize, target: &Case, path: &Path) -> Option<Row<'a>> {
+    let fields = match row.patterns[col] {
+        None | Some(Pattern::Wildcard(_) | Pattern::Bind(_)) => vec![None; target.arity()],
+        pattern if case(pattern).as_ref() == Some(target) => match pattern {
+            Some(Pattern::Cons { args, .. }) => args.iter().map(Some).collect(),
+            _ => Vec::new(),
+        },
+        _ => return None,
+    };
+
+    let mut row = take(row.clone(), col, path);
+    row.patterns.splice(0..0, fields);
+    Some(row)
+}
@@ -0,0 +1,430 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    context::Context,
+    json::Json,
+    source::{self, SourceFile},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let s = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub context: Context,
+    pub message: String,
+    pub primary: bool,
+    /// Keeps the file the label points into, so it can be rendered after
+    /// the file is removed
+    _hold: Option<source::Hold>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, context: Context, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            context,
+            message: message.into(),
+            primary: true,
+            _hold: source::hold(context.file),
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, context: Context, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            context,
+            message: message.into(),
+            primary: false,
+            _hold: source::hold(context.file),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// The span the diagnostic is about, if it points anywhere
+    pub fn primary(&self) -> Option<Context> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or(self.labels.first())
+            .map(|label| label.context)
+    }
+
+    pub fn render(&self, color: bool) -> String {
+        let mut out = Renderer {
+            out: String::new(),
+            color,
+        };
+        out.diagnostic(self);
+        out.out
+    }
+}
+
+/// Location fields of a span: 1-based lines and columns in chars, with the
+/// end byte and column exclusive
+fn span_fields(context: Context) -> Vec<(&'static str, Json)> {
+    let file = source::get(context.file);
+    let end = context.start + context.len;
+    let (line_start, column_start) = file.line_col(context.start);
+    let (line_end, column_end) = file.line_col(end);
+
+    vec![
+        ("file", file.name.as_str().into()),
+        ("byte_start", context.start.into()),
+        ("byte_end", end.into()),
+        ("line_start", line_start.into()),
+        ("column_start", column_start.into()),
+        ("line_end", line_end.into()),
+        ("column_end", column_end.into()),
+    ]
+}
+
+impl Diagnostic {
+    /// One JSON object for `--error-format=json`; the location fields repeat
+    /// those of the primary label, and are null when there's no label
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("severity", self.severity.to_string().into()),
+            ("code", self.code.into()),
+            ("message", self.message.as_str().into()),
+        ];
+
+        match self.primary() {
+            Some(context) => fields.extend(span_fields(context)),
+            None => fields.extend(
+                ["file", "byte_start", "byte_end", "line_start", "column_start", "line_end", "column_end"]
+                    .map(|key| (key, Json::Null)),
+            ),
+        }
+
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                let mut fields = span_fields(label.context);
+                fields.push(("message", label.message.as_str().into()));
+                fields.push(("primary", label.primary.into()));
+                Json::object(fields)
+            })
+            .collect::<Vec<_>>();
+
+        fields.push(("labels", labels.into()));
+        fields.push(("notes", self.notes.clone().into()));
+        fields.push(("help", self.help.clone().into()));
+        Json::object(fields)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+const TAB_WIDTH: usize = 4;
+
+/// Lines of a multi-line span shown on either side of the elided middle
+const CONTEXT_LINES: usize = 2;
+
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+/// A label resolved to 1-based lines and 0-based display columns
+struct Span<'a> {
+    label: &'a Label,
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl Span<'_> {
+    fn multiline(&self) -> bool {
+        self.start.0 != self.end.0
+    }
+}
+
+struct Renderer {
+    out: String,
+    color: bool,
+}
+
+impl Renderer {
+    fn paint(&mut self, style: &str, text: &str) {
+        if self.color && !text.is_empty() {
+            self.out.push_str(style);
+            self.out.push_str(text);
+            self.out.push_str(RESET);
+        } else {
+            self.out.push_str(text);
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+
+    fn label_style(&self, severity: Severity, label: &Label) -> &'static str {
+        if label.primary {
+            Self::severity_style(severity)
+        } else {
+            BLUE
+        }
+    }
+
+    fn diagnostic(&mut self, diag: &Diagnostic) {
+        let style = Self::severity_style(diag.severity);
+        let mut header = diag.severity.to_string();
+        if let Some(code) = diag.code {
+            header += &format!("[{}]", code);
+        }
+        self.paint(style, &header);
+        self.paint(BOLD, &format!(": {}", diag.message));
+        self.out.push('\n');
+
+        // labels are grouped by file, the primary label's file first
+        let mut files = Vec::new();
+        let primary = diag.primary();
+        for file in primary.iter().map(|c| c.file).chain(diag.labels.iter().map(|l| l.context.file)) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        let max_line = diag
+            .labels
+            .iter()
+            .map(|l| source::get(l.context.file).line_col(l.context.start + l.context.len).0)
+            .max()
+            .unwrap_or(0);
+        let gutter = max_line.to_string().len();
+
+        for file in files {
+            let source = source::get(file);
+            let labels = diag.labels.iter().filter(|l| l.context.file == file).collect::<Vec<_>>();
+            let anchor = primary.filter(|c| c.file == file).unwrap_or(labels[0].context);
+            self.file(diag.severity, &source, &labels, anchor, gutter);
+        }
+
+        let trailer = !diag.notes.is_empty() || !diag.help.is_empty();
+        if !diag.labels.is_empty() && trailer {
+            self.gutter(gutter, None);
+            self.out.push('\n');
+        }
+
+        for (kind, text) in diag
+            .notes
+            .iter()
+            .map(|n| ("note", n))
+            .chain(diag.help.iter().map(|h| ("help", h)))
+        {
+            self.out.push_str(&" ".repeat(gutter + 1));
+            self.paint(BLUE, "=");
+            self.paint(BOLD, &format!(" {}", kind));
+            self.out.push_str(&format!(": {}\n", text));
+        }
+    }
+
+    fn gutter(&mut self, width: usize, line: Option<usize>) {
+        let number = line.map_or(String::new(), |l| l.to_string());
+        self.paint(BLUE, &format!("{:>width$} |", number, width = width));
+    }
+
+    fn position(source: &SourceFile, offset: usize) -> (usize, usize) {
+        let (line, _) = source.line_col(offset);
+        let start = source.line_start(line);
+        let offset = offset.min(source.src.len()).max(start);
+        (line, display_width(&source.src[start..offset]))
+    }
+
+    fn file(&mut self, severity: Severity, source: &SourceFile, labels: &[&Label], anchor: Context, gutter: usize) {
+        let (line, col) = source.line_col(anchor.start);
+        self.out.push_str(&" ".repeat(gutter));
+        self.paint(BLUE, "-->");
+        self.out.push_str(&format!(" {}:{}:{}\n", source.name, line, col));
+        self.gutter(gutter, None);
+        self.out.push('\n');
+
+        let mut spans = labels
+            .iter()
+            .map(|&label| {
+                let start = Self::position(source, label.context.start);
+                let mut end = Self::position(source, label.context.start + label.context.len);
+
+                if label.context.len == 0 {
+                    end = (start.0, start.1 + 1);
+                } else if end.0 > start.0 && end.1 == 0 {
+                    // a span ending in a newline ends on the previous line
+                    end = (end.0 - 1, display_width(source.line(end.0 - 1)) + 1);
+                }
+
+                Span { label, start, end }
+            })
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|s| (s.start, !s.label.primary));
+
+        // multi-line spans each get a column of the bar to the left of the source
+        let bars = spans.iter().filter(|s| s.multiline()).count();
+
+        let mut lines = Vec::new();
+        for span in &spans {
+            if span.multiline() && span.end.0 - span.start.0 > 2 * CONTEXT_LINES {
+                lines.extend(span.start.0..span.start.0 + CONTEXT_LINES);
+                lines.extend(span.end.0 + 1 - CONTEXT_LINES..=span.end.0);
+            } else {
+                lines.extend(span.start.0..=span.end.0);
+            }
+        }
+        lines.sort();
+        lines.dedup();
+
+        let mut prev = None;
+        for line in lines {
+            if prev.is_some_and(|p| p + 1 != line) {
+                self.paint(BLUE, "...");
+                self.out.push('\n');
+            }
+            prev = Some(line);
+
+            self.gutter(gutter, Some(line));
+            self.out.push(' ');
+            self.bars(severity, &spans, line, bars, false);
+            self.out.push_str(&source.line(line).replace('\t', &" ".repeat(TAB_WIDTH)));
+            self.out.push('\n');
+
+            for span in spans.iter().filter(|s| !s.multiline() && s.start.0 == line) {
+                let style = self.label_style(severity, span.label);
+                let mark = if span.label.primary { "^" } else { "-" };
+
+                self.gutter(gutter, None);
+                self.out.push(' ');
+                self.bars(severity, &spans, line, bars, false);
+                self.out.push_str(&" ".repeat(span.start.1));
+                self.paint(style, &mark.repeat((span.end.1 - span.start.1).max(1)));
+                if !span.label.message.is_empty() {
+                    self.paint(style, &format!(" {}", span.label.message));
+                }
+                self.out.push('\n');
+            }
+
+            for (bar, span) in spans.iter().filter(|s| s.multiline()).enumerate() {
+                let style = self.label_style(severity, span.label);
+                let mark = if span.label.primary { "^" } else { "-" };
+
+                if span.start.0 == line {
+                    // `_____^` from the bar over to where the span starts
+                    self.gutter(gutter, None);
+                    self.out.push(' ');
+                    self.bars(severity, &spans, line, bar, true);
+                    self.out.push(' ');
+                    self.paint(style, &"_".repeat(2 * (bars - bar) - 1 + span.start.1));
+                    self.paint(style, mark);
+                    self.out.push('\n');
+                } else if span.end.0 == line {
+                    // `|_____^ message` from the bar to where it ends
+                    self.gutter(gutter, None);
+                    self.out.push(' ');
+                    self.bars(severity, &spans, line, bar, true);
+                    self.paint(style, "|");
+                    self.paint(style, &"_".repeat(2 * (bars - bar) - 1 + span.end.1 - 1));
+                    self.paint(style, mark);
+                    if !span.label.message.is_empty() {
+                        self.paint(style, &format!(" {}", span.label.message));
+                    }
+                    self.out.push('\n');
+                }
+            }
+        }
+    }
+
+    /// Draws the first `count` bar columns for `line`, each `|` where its
+    /// span covers the line; `after` is set on the rows that open and close
+    /// multi-line spans, by which point spans starting on `line` are open and
+    /// those ending on it are closed
+    fn bars(&mut self, severity: Severity, spans: &[Span], line: usize, count: usize, after: bool) {
+        let multiline = spans.iter().filter(|s| s.multiline());
+        for (i, span) in multiline.enumerate() {
+            if i >= count {
+                break;
+            }
+
+            let inside = if after {
+                span.start.0 <= line && line < span.end.0
+            } else {
+                span.start.0 < line && line <= span.end.0
+            };
+
+            if inside {
+                let style = self.label_style(severity, span.label);
+                self.paint(style, "|");
+                self.out.push(' ');
+            } else {
+                self.out.push_str("  ");
+            }
+        }
+    }
+}
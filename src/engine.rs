@@ -0,0 +1,502 @@
+//! Embedding snd in Rust programs
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    ast::s0::Tree,
+    diagnostic::Diagnostic,
+    exhaustive,
+    lexer::{LexError, Lexer},
+    parser::{ParseError, Parser},
+    source,
+    types::{self, Type},
+    util::Symbol,
+    vm::{
+        self,
+        heap::{Data, Heap, Object},
+        Vm,
+    },
+};
+
+/// A value passed to or returned from snd, owned by Rust
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    Char(char),
+    /// A value of a `data` type, like `Cons(1, Nil)`
+    Data { cons: String, fields: Vec<Value> },
+    /// A function or constructor, which Rust can't look into or pass back
+    Function,
+}
+
+impl Value {
+    pub fn data(cons: &str, fields: impl IntoIterator<Item = Value>) -> Value {
+        Value::Data {
+            cons: cons.to_string(),
+            fields: fields.into_iter().collect(),
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> Option<char> {
+        match self {
+            Value::Char(c) => Some(*c),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        enum Item<'a> {
+            Value(&'a Value),
+            Text(&'static str),
+        }
+
+        // written from a stack, since lists can be very long
+        let mut work = vec![Item::Value(self)];
+        while let Some(item) = work.pop() {
+            match item {
+                Item::Text(text) => f.write_str(text)?,
+                Item::Value(Value::Data { cons, fields }) => {
+                    f.write_str(cons)?;
+                    if fields.is_empty() {
+                        continue;
+                    }
+
+                    // pushed in reverse, to come off in order
+                    work.push(Item::Text(")"));
+                    for (i, field) in fields.iter().enumerate().rev() {
+                        work.push(Item::Value(field));
+                        if i > 0 {
+                            work.push(Item::Text(", "));
+                        }
+                    }
+                    work.push(Item::Text("("));
+                }
+                Item::Value(Value::Unit) => f.write_str("()")?,
+                Item::Value(Value::Int(n)) => write!(f, "{}", n)?,
+                Item::Value(Value::Bool(b)) => write!(f, "{}", b)?,
+                Item::Value(Value::Str(s)) => write!(f, "{:?}", s)?,
+                Item::Value(Value::Char(c)) => write!(f, "{:?}", c)?,
+                Item::Value(Value::Function) => f.write_str("<fn>")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Unit
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Int(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Str(s)
+    }
+}
+
+impl From<char> for Value {
+    fn from(c: char) -> Value {
+        Value::Char(c)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The source, or the call, didn't get past checking
+    Compile,
+    /// The program stopped with an error while running
+    Runtime,
+}
+
+/// Why loading or calling failed
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Error {
+    fn new(kind: ErrorKind, diagnostics: Vec<Diagnostic>) -> Error {
+        Error { kind, diagnostics }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, diag) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diag)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Loads programs and calls their functions, keeping the definitions of
+/// everything loaded so far
+///
+/// ```
+/// let mut engine = snd_language::Engine::new();
+/// engine.load("add.snd", "let add = fn(a, b) = a + b").unwrap();
+/// let sum = engine.call("add", &[1.into(), 2.into()]).unwrap();
+/// assert_eq!(sum.as_int(), Some(3));
+/// ```
+pub struct Engine {
+    types: types::Session,
+    matches: exhaustive::Session,
+    vm: Vm,
+    warnings: Vec<Diagnostic>,
+}
+
+/// A program [`Engine::load_typed`] checked and ran
+#[derive(Debug)]
+pub struct Loaded {
+    /// The value of its last expression
+    pub value: Value,
+    /// The names it defined at the top level, with their types
+    pub definitions: Vec<(String, String)>,
+}
+
+struct Saved {
+    types: types::Saved,
+    matches: exhaustive::Saved,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            types: types::Session::new(),
+            matches: exhaustive::Session::default(),
+            vm: Vm::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Checks and runs a program, returning the value of its last
+    /// expression; its definitions stay for later programs and calls,
+    /// unless it fails while running
+    pub fn load(&mut self, name: &str, src: &str) -> Result<Value, Error> {
+        self.load_typed(name, src).map(|loaded| loaded.value)
+    }
+
+    /// Like [`Engine::load`], also returning the types of what the program
+    /// defined
+    pub fn load_typed(&mut self, name: &str, src: &str) -> Result<Loaded, Error> {
+        let lexer = Lexer::from_source(name, src);
+        let file = lexer.file();
+
+        // diagnostics hold the source they point into, but definitions can
+        // fail at runtime later, pointing into it too
+        let result = self.run(lexer);
+        let defines = match &result {
+            Ok((Tree::Block { items, .. }, _)) => items.iter().any(|item| matches!(item, Tree::Let { .. } | Tree::Data(_))),
+            _ => false,
+        };
+        if !defines {
+            source::remove(file);
+        }
+        result.map(|(_, loaded)| loaded)
+    }
+
+    /// Checks a program without running it, keeping none of its
+    /// definitions
+    pub fn check(&mut self, name: &str, src: &str) -> Result<(), Error> {
+        let lexer = Lexer::from_source(name, src);
+        let file = lexer.file();
+        let result = self.parse(lexer).and_then(|tree| {
+            let saved = self.save();
+            let result = self.types.check(&tree).map_err(type_errors);
+            if result.is_ok() {
+                let warnings = self.matches.check(&tree);
+                self.warnings.extend(warnings);
+            }
+            self.restore(saved);
+            result.map(drop)
+        });
+        source::remove(file);
+        result
+    }
+
+    /// The type of an expression, checked against the definitions so far
+    /// without running it or keeping anything it defines
+    pub fn type_of(&mut self, name: &str, src: &str) -> Result<String, Error> {
+        let lexer = Lexer::from_source(name, src);
+        let file = lexer.file();
+        let result = self
+            .parse(lexer)
+            .and_then(|tree| self.types.type_of(&tree).map_err(type_errors))
+            .map(|scheme| scheme.to_string());
+        source::remove(file);
+        result
+    }
+
+    /// Calls a top-level function, with arguments type checked against it
+    pub fn call(&mut self, function: &str, args: &[Value]) -> Result<Value, Error> {
+        check_call(&mut self.types, function, args).map_err(|diag| Error::new(ErrorKind::Compile, vec![diag]))?;
+
+        // a definition whose program failed while running has no value
+        let Some(callee) = Symbol::get(function).and_then(|name| self.vm.global(name)) else {
+            let diag = Diagnostic::error(format!("`{}` is not defined", function));
+            return Err(Error::new(ErrorKind::Runtime, vec![diag]));
+        };
+        let args = args.iter().map(|arg| write_value(&mut self.vm, arg)).collect();
+        match self.vm.apply(callee, args) {
+            Ok(value) => Ok(read_value(&self.vm, value)),
+            Err(err) => Err(Error::new(ErrorKind::Runtime, vec![err.diagnostic()])),
+        }
+    }
+
+    /// The VM's heap, to tune or inspect its garbage collector
+    pub fn heap(&mut self) -> &mut Heap {
+        &mut self.vm.heap
+    }
+
+    /// Takes the warnings reported since the last time they were taken
+    pub fn warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn parse(&mut self, lexer: Lexer) -> Result<Tree, Error> {
+        let tokens = lexer
+            .lex()
+            .map_err(|errors| Error::new(ErrorKind::Compile, errors.iter().map(LexError::diagnostic).collect()))?;
+
+        let mut parser = Parser::new(tokens);
+        let tree = parser.parse();
+        self.warnings.extend(parser.warnings());
+        tree.map_err(|errors| Error::new(ErrorKind::Compile, errors.iter().map(ParseError::diagnostic).collect()))
+    }
+
+    fn run(&mut self, lexer: Lexer) -> Result<(Tree, Loaded), Error> {
+        let tree = self.parse(lexer)?;
+
+        let saved = self.save();
+        let typed = self.types.check(&tree).map_err(type_errors)?;
+        let warnings = self.matches.check(&tree);
+        self.warnings.extend(warnings);
+
+        let program = self.vm.compile(&tree);
+        match self.vm.run(program) {
+            Ok(value) => {
+                let value = read_value(&self.vm, value);
+                let definitions = definitions(&tree, &typed);
+                Ok((tree, Loaded { value, definitions }))
+            }
+            Err(err) => {
+                // what failed to run isn't defined
+                self.restore(saved);
+                Err(Error::new(ErrorKind::Runtime, vec![err.diagnostic()]))
+            }
+        }
+    }
+
+    /// Where the definitions are now, to go back to if a program that
+    /// checks doesn't run to the end; the VM keeps its own part
+    fn save(&mut self) -> Saved {
+        self.vm.save();
+        Saved {
+            types: self.types.save(),
+            matches: self.matches.save(),
+        }
+    }
+
+    fn restore(&mut self, saved: Saved) {
+        self.types.restore(saved.types);
+        self.matches.restore(saved.matches);
+        self.vm.restore();
+    }
+}
+
+fn type_errors(errors: Vec<types::TypeError>) -> Error {
+    Error::new(ErrorKind::Compile, errors.iter().map(types::TypeError::diagnostic).collect())
+}
+
+/// The top-level names a program defines, with their types
+fn definitions(tree: &Tree, typed: &types::Typed) -> Vec<(String, String)> {
+    let Tree::Block { items, .. } = tree else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Tree::Let { name, .. } => {
+                let (_, scheme) = typed.bindings.iter().rev().find(|(ident, _)| ident.context == name.context)?;
+                Some((name.to_string(), scheme.to_string()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Checks that the top-level `function` can be called with `args`
+fn check_call(types: &mut types::Session, function: &str, args: &[Value]) -> Result<(), Diagnostic> {
+    types.scoped(|types| {
+        let Some(ty) = types.instantiate(function) else {
+            return Err(Diagnostic::error(format!("`{}` is not defined", function)).with_code("E0201"));
+        };
+        let params = match ty {
+            Type::Fn(params, _) => params,
+            ty => {
+                let note = format!("`{}` is {}, not a function", function, types.show(&ty));
+                return Err(Diagnostic::error("mismatched types").with_code("E0200").with_note(note));
+            }
+        };
+        if params.len() != args.len() {
+            let message = format!("function takes {} arguments but {} were given", params.len(), args.len());
+            return Err(Diagnostic::error(message).with_code("E0205"));
+        }
+
+        for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+            let ty = value_type(types, arg)?;
+            types.expect_value(&ty, param, || format!("argument {} of `{}`", i + 1, function))?;
+        }
+        Ok(())
+    })
+}
+
+/// The type of a value passed to snd
+fn value_type(types: &mut types::Session, value: &Value) -> Result<Type, Diagnostic> {
+    let (cons, fields) = match value {
+        Value::Unit => return Ok(types::con("Unit")),
+        Value::Int(_) => return Ok(types::con("Int")),
+        Value::Bool(_) => return Ok(types::con("Bool")),
+        Value::Str(_) => return Ok(types::con("String")),
+        Value::Char(_) => return Ok(types::con("Char")),
+        Value::Function => return Err(Diagnostic::error("functions cannot be passed to snd")),
+        Value::Data { cons, fields } => (cons, fields),
+    };
+
+    let Some(ty) = types.instantiate_constructor(cons) else {
+        return Err(Diagnostic::error(format!("unknown constructor `{}`", cons)).with_code("E0203"));
+    };
+    let arity = |expected: usize| {
+        let message = format!("constructor `{}` has {} fields but {} were given", cons, expected, fields.len());
+        Diagnostic::error(message).with_code("E0204")
+    };
+    match ty {
+        Type::Fn(params, result) if params.len() == fields.len() => {
+            for (param, field) in params.iter().zip(fields) {
+                let ty = value_type(types, field)?;
+                types.expect_value(&ty, param, || format!("a field of `{}`", cons))?;
+            }
+            Ok(*result)
+        }
+        Type::Fn(params, _) => Err(arity(params.len())),
+        ty if fields.is_empty() => Ok(ty),
+        _ => Err(arity(0)),
+    }
+}
+
+/// Copies a value into the VM, whose type has been checked
+fn write_value(vm: &mut Vm, value: &Value) -> vm::Value {
+    match value {
+        Value::Unit => vm::Value::Unit,
+        Value::Int(n) => vm::Value::Int(*n),
+        Value::Bool(b) => vm::Value::Bool(*b),
+        Value::Str(s) => vm::Value::Str(s.as_str().into()),
+        Value::Char(c) => vm::Value::Char(*c),
+        Value::Data { cons, fields } => {
+            let fields = fields.iter().map(|field| write_value(vm, field)).collect();
+            let cons = Symbol::get(cons).expect("checked constructors have symbols");
+            vm::Value::Data(vm.heap.alloc(Object::Data(Data { cons, fields })))
+        }
+        Value::Function => unreachable!("functions don't type check as arguments"),
+    }
+}
+
+/// Copies a value out of the VM
+fn read_value(vm: &Vm, value: vm::Value) -> Value {
+    enum Item {
+        Value(vm::Value),
+        /// Makes data from the last `n` values read
+        Data(&'static Symbol, usize),
+    }
+
+    // built bottom up, since lists can be very long
+    let mut work = vec![Item::Value(value)];
+    let mut values = Vec::new();
+    while let Some(item) = work.pop() {
+        let value = match item {
+            Item::Data(cons, n) => {
+                let fields = values.split_off(values.len() - n);
+                Value::data(cons.name, fields)
+            }
+            Item::Value(vm::Value::Data(gc)) => {
+                let data = vm.heap.data(gc);
+                work.push(Item::Data(data.cons, data.fields.len()));
+                work.extend(data.fields.iter().rev().cloned().map(Item::Value));
+                continue;
+            }
+            Item::Value(vm::Value::Unit) => Value::Unit,
+            Item::Value(vm::Value::Int(n)) => Value::Int(n),
+            Item::Value(vm::Value::Bool(b)) => Value::Bool(b),
+            Item::Value(vm::Value::Str(s)) => Value::Str(s.to_string()),
+            Item::Value(vm::Value::Char(c)) => Value::Char(c),
+            Item::Value(vm::Value::Constructor { .. } | vm::Value::Closure(_)) => Value::Function,
+        };
+        values.push(value);
+    }
+    values.pop().unwrap()
+}
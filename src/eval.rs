@@ -0,0 +1,424 @@
+//! Evaluates the AST directly, the reference for what the VM computes
+//!
+//! Values are reference counted rather than on a heap, and calls recurse on
+//! the Rust stack, so deep recursion runs out of stack here where the VM's
+//! tail calls don't.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{
+    ast::s0::*,
+    context::Context,
+    decision::{self, Case, Constructors, Decision},
+    util::Symbol,
+    vm::value::{RuntimeError, RuntimeErrorKind},
+};
+
+use RuntimeErrorKind::*;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Str(Rc<str>),
+    Char(char),
+    /// A fully applied data constructor
+    Data(Rc<Data>),
+    /// A data constructor still waiting for its fields
+    Constructor { name: &'static Symbol, arity: usize },
+    Closure(Rc<Closure>),
+}
+
+#[derive(Debug)]
+pub struct Data {
+    pub cons: &'static Symbol,
+    pub fields: Vec<Value>,
+}
+
+pub struct Closure {
+    params: Vec<Ident>,
+    body: Rc<Tree>,
+    env: Rc<Env>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // the environment is usually cyclic
+        write!(f, "Closure({:?})", self.params)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Data(data) => {
+                write!(f, "{}", data.cons.name)?;
+                if !data.fields.is_empty() {
+                    write!(f, "(")?;
+                    for (i, field) in data.fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", field)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Constructor { name, .. } => write!(f, "<constructor {}>", name.name),
+            Value::Closure(_) => write!(f, "<fn>"),
+        }
+    }
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Int(_) => "Int",
+            Value::Bool(_) => "Bool",
+            Value::Str(_) => "String",
+            Value::Char(_) => "Char",
+            Value::Data(_) => "data value",
+            Value::Constructor { .. } | Value::Closure(_) => "function",
+        }
+    }
+
+    /// Structural equality, `None` if functions had to be compared
+    fn equals(&self, other: &Value) -> Option<bool> {
+        let eq = match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Data(a), Value::Data(b)) => {
+                if a.cons != b.cons {
+                    return Some(false);
+                }
+                for (a, b) in a.fields.iter().zip(&b.fields) {
+                    if !a.equals(b)? {
+                        return Some(false);
+                    }
+                }
+                true
+            }
+            (Value::Constructor { .. } | Value::Closure(_), _) => return None,
+            _ => false,
+        };
+        Some(eq)
+    }
+}
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+fn error<T>(context: Context, kind: RuntimeErrorKind) -> Result<T> {
+    Err(RuntimeError { kind, context })
+}
+
+/// A scope; `let`s add to the innermost scope in place, so functions can
+/// refer to themselves and to later top-level definitions
+#[derive(Default)]
+pub struct Env {
+    vars: RefCell<HashMap<&'static Symbol, Value>>,
+    parent: Option<Rc<Env>>,
+}
+
+impl Env {
+    pub fn new() -> Rc<Env> {
+        Rc::new(Env::default())
+    }
+
+    fn child(parent: &Rc<Env>) -> Rc<Env> {
+        Rc::new(Env {
+            vars: RefCell::default(),
+            parent: Some(parent.clone()),
+        })
+    }
+
+    fn define(&self, name: &'static Symbol, value: Value) {
+        self.vars.borrow_mut().insert(name, value);
+    }
+
+    fn lookup(&self, name: &'static Symbol) -> Option<Value> {
+        match self.vars.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.lookup(name),
+        }
+    }
+}
+
+/// Evaluates a program in `env`, where its top-level definitions go
+pub fn eval(tree: &Tree, env: &Rc<Env>) -> Result<Value> {
+    let evaluator = Evaluator::default();
+    match tree {
+        Tree::Block { items, .. } => evaluator.items(items, env),
+        tree => evaluator.eval(tree, env),
+    }
+}
+
+#[derive(Default)]
+struct Evaluator {
+    /// The `data` types in scope, which the `match`es are compiled against
+    constructors: RefCell<Constructors>,
+    /// Compiled `match`es, by the address of their tree
+    decisions: RefCell<HashMap<*const Tree, Rc<Decision>>>,
+}
+
+impl Evaluator {
+    fn items(&self, items: &[Tree], env: &Rc<Env>) -> Result<Value> {
+        let mut value = Value::Unit;
+        for item in items {
+            value = self.eval(item, env)?;
+        }
+        Ok(value)
+    }
+
+    fn eval(&self, tree: &Tree, env: &Rc<Env>) -> Result<Value> {
+        match tree {
+            Tree::Block { items, .. } => {
+                // `data` in the block is only in scope until its end
+                let outer = self.constructors.borrow().clone();
+                let value = self.items(items, &Env::child(env));
+                *self.constructors.borrow_mut() = outer;
+                value
+            }
+            Tree::Data(def) => {
+                let all = Rc::new(def.cons.iter().map(|cons| (cons.name.name, cons.fields.len())).collect::<Vec<_>>());
+                for cons in &def.cons {
+                    let name = cons.name.name;
+                    self.constructors.borrow_mut().insert(name, all.clone());
+
+                    let value = match cons.fields.len() {
+                        0 => Value::Data(Rc::new(Data {
+                            cons: name,
+                            fields: Vec::new(),
+                        })),
+                        arity => Value::Constructor { name, arity },
+                    };
+                    env.define(name, value);
+                }
+                Ok(Value::Unit)
+            }
+            Tree::Let { name, value, .. } => {
+                let value = self.eval(value, env)?;
+                env.define(name.name, value);
+                Ok(Value::Unit)
+            }
+            Tree::Fn { params, body, .. } => Ok(Value::Closure(Rc::new(Closure {
+                params: params.clone(),
+                body: body.clone(),
+                env: env.clone(),
+            }))),
+            Tree::Match {
+                scrutinee,
+                arms,
+                context,
+            } => {
+                let value = self.eval(scrutinee, env)?;
+                let decision = self
+                    .decisions
+                    .borrow_mut()
+                    .entry(tree as *const Tree)
+                    .or_insert_with(|| Rc::new(decision::compile(arms, &self.constructors.borrow())))
+                    .clone();
+
+                match select(&decision, &value) {
+                    Some((arm, bindings)) => {
+                        let scope = Env::child(env);
+                        for (name, path) in bindings {
+                            scope.define(name, field(&value, path).clone());
+                        }
+                        self.eval(&arms[arm].body, &scope)
+                    }
+                    None => error(*context, NoMatch(value.to_string())),
+                }
+            }
+            Tree::If {
+                cond, then, els, ..
+            } => match self.eval(cond, env)? {
+                Value::Bool(true) => self.eval(then, env),
+                Value::Bool(false) => self.eval(els, env),
+                other => error(
+                    cond.context(),
+                    Invalid(format!("expected a Bool, found {}", other.type_name())),
+                ),
+            },
+            Tree::Call {
+                func,
+                args,
+                context,
+            } => {
+                let func = self.eval(func, env)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, env))
+                    .collect::<Result<Vec<_>>>()?;
+                self.apply(func, args, *context)
+            }
+            Tree::Binary {
+                op,
+                lhs,
+                rhs,
+                context,
+            } => self.binary(*op, lhs, rhs, *context, env),
+            Tree::Unary {
+                op,
+                operand,
+                context,
+            } => match (op, self.eval(operand, env)?) {
+                (UnOp::Neg, Value::Int(n)) => match n.checked_neg() {
+                    Some(n) => Ok(Value::Int(n)),
+                    None => error(*context, Overflow),
+                },
+                (UnOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                (op, value) => error(
+                    *context,
+                    Invalid(format!("cannot apply `{}` to {}", op, value.type_name())),
+                ),
+            },
+            Tree::Var(ident) => match env.lookup(ident.name) {
+                Some(value) => Ok(value),
+                None => error(ident.context, Undefined(ident.name)),
+            },
+            Tree::IntLit { value, .. } => Ok(Value::Int(*value)),
+            Tree::BoolLit { value, .. } => Ok(Value::Bool(*value)),
+            Tree::StrLit { value, .. } => Ok(Value::Str(value.clone())),
+            Tree::CharLit { value, .. } => Ok(Value::Char(*value)),
+            Tree::Error(context) => error(*context, Invalid("this part of the program has a syntax error".to_string())),
+        }
+    }
+
+    fn apply(&self, func: Value, args: Vec<Value>, context: Context) -> Result<Value> {
+        match func {
+            Value::Closure(closure) => {
+                if closure.params.len() != args.len() {
+                    let message = format!(
+                        "function takes {} arguments but {} were given",
+                        closure.params.len(),
+                        args.len()
+                    );
+                    return error(context, Invalid(message));
+                }
+
+                let scope = Env::child(&closure.env);
+                for (param, arg) in closure.params.iter().zip(args) {
+                    scope.define(param.name, arg);
+                }
+                self.eval(&closure.body, &scope)
+            }
+            Value::Constructor { name, arity } => {
+                if arity != args.len() {
+                    let message = format!(
+                        "constructor `{}` takes {} fields but {} were given",
+                        name.name,
+                        arity,
+                        args.len()
+                    );
+                    return error(context, Invalid(message));
+                }
+                Ok(Value::Data(Rc::new(Data {
+                    cons: name,
+                    fields: args,
+                })))
+            }
+            other => error(context, Invalid(format!("cannot call {}", other.type_name()))),
+        }
+    }
+
+    fn binary(&self, op: BinOp, lhs: &Tree, rhs: &Tree, context: Context, env: &Rc<Env>) -> Result<Value> {
+        use BinOp::*;
+
+        let lhs = self.eval(lhs, env)?;
+
+        // `&&` and `||` only evaluate their right side when needed
+        if let (And | Or, Value::Bool(b)) = (op, &lhs) {
+            if *b == (op == Or) {
+                return Ok(Value::Bool(*b));
+            }
+        }
+
+        let rhs = self.eval(rhs, env)?;
+
+        let value = match (op, &lhs, &rhs) {
+            (Eq | NotEq, _, _) => match lhs.equals(&rhs) {
+                Some(eq) => Value::Bool(eq == (op == Eq)),
+                None => return error(context, Incomparable),
+            },
+            (And | Or, Value::Bool(_), Value::Bool(b)) => Value::Bool(*b),
+            (_, Value::Int(a), Value::Int(b)) => {
+                let (a, b) = (*a, *b);
+                let arith = match op {
+                    Add => a.checked_add(b),
+                    Sub => a.checked_sub(b),
+                    Mul => a.checked_mul(b),
+                    Div | Rem if b == 0 => return error(context, DivisionByZero),
+                    Div => a.checked_div(b),
+                    Rem => a.checked_rem(b),
+                    Lt => return Ok(Value::Bool(a < b)),
+                    Le => return Ok(Value::Bool(a <= b)),
+                    Gt => return Ok(Value::Bool(a > b)),
+                    Ge => return Ok(Value::Bool(a >= b)),
+                    Eq | NotEq | And | Or => unreachable!(),
+                };
+                match arith {
+                    Some(n) => Value::Int(n),
+                    None => return error(context, Overflow),
+                }
+            }
+            _ => {
+                let message = format!(
+                    "cannot apply `{}` to {} and {}",
+                    op,
+                    lhs.type_name(),
+                    rhs.type_name()
+                );
+                return error(context, Invalid(message));
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+/// The value at `path` inside `value`, which the decision tree has tested
+/// has those fields
+fn field<'a>(value: &'a Value, path: &[usize]) -> &'a Value {
+    path.iter().fold(value, |value, &i| match value {
+        Value::Data(data) => &data.fields[i],
+        _ => unreachable!("path into a value without fields"),
+    })
+}
+
+/// Walks `decision` for `value`, testing every case, including the last one
+/// of a switch with no default; returns the chosen arm and its bindings
+fn select<'a>(decision: &'a Decision, value: &Value) -> Option<(usize, &'a [(&'static Symbol, decision::Path)])> {
+    match decision {
+        Decision::Fail => None,
+        Decision::Leaf { arm, bindings } => Some((*arm, bindings)),
+        Decision::Switch { path, cases, default } => {
+            let found = field(value, path);
+            let next = cases.iter().find(|(case, _)| match (case, found) {
+                (Case::Cons { name, .. }, Value::Data(data)) => data.cons == *name,
+                (Case::Int(a), Value::Int(b)) => a == b,
+                (Case::Bool(a), Value::Bool(b)) => a == b,
+                (Case::Str(a), Value::Str(b)) => a == b,
+                (Case::Char(a), Value::Char(b)) => a == b,
+                _ => false,
+            });
+            match next {
+                Some((_, next)) => select(next, value),
+                None => select(default.as_deref()?, value),
+            }
+        }
+    }
+}
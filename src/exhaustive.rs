@@ -0,0 +1,263 @@
+//! Exhaustiveness and redundancy checking for `match`, over a matrix of
+//! patterns with one row per arm
+
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{
+    ast::s0::*,
+    decision::{self, Constructors},
+    diagnostic::Diagnostic,
+    util::Symbol,
+};
+
+/// A pattern with variables and spans dropped
+#[derive(Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+#[derive(Clone, PartialEq)]
+enum Ctor {
+    Cons(&'static Symbol),
+    Bool(bool),
+    Int(i64),
+    Str(Rc<str>),
+    Char(char),
+}
+
+impl Display for Pat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Pat::Wild => write!(f, "_"),
+            Pat::Ctor(Ctor::Cons(name), args) => {
+                write!(f, "{}", name.name)?;
+                if !args.is_empty() {
+                    write!(f, "(")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", arg)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Pat::Ctor(Ctor::Bool(b), _) => write!(f, "{}", b),
+            Pat::Ctor(Ctor::Int(n), _) => write!(f, "{}", n),
+            Pat::Ctor(Ctor::Str(s), _) => write!(f, "{:?}", s),
+            Pat::Ctor(Ctor::Char(c), _) => write!(f, "{:?}", c),
+        }
+    }
+}
+
+fn lower(pattern: &Pattern) -> Pat {
+    match pattern {
+        Pattern::Wildcard(_) | Pattern::Bind(_) => Pat::Wild,
+        Pattern::Cons { name, args, .. } => Pat::Ctor(Ctor::Cons(name.name), args.iter().map(lower).collect()),
+        Pattern::IntLit { value, .. } => Pat::Ctor(Ctor::Int(*value), Vec::new()),
+        Pattern::BoolLit { value, .. } => Pat::Ctor(Ctor::Bool(*value), Vec::new()),
+        Pattern::StrLit { value, .. } => Pat::Ctor(Ctor::Str(value.clone()), Vec::new()),
+        Pattern::CharLit { value, .. } => Pat::Ctor(Ctor::Char(*value), Vec::new()),
+    }
+}
+
+type Row = Vec<Pat>;
+
+/// Checks every `match` in a type-correct program, returning warnings
+pub fn check(tree: &Tree) -> Vec<Diagnostic> {
+    Session::default().check(tree)
+}
+
+/// Constructors kept from one check to the next, as in the REPL
+#[derive(Default)]
+pub struct Session {
+    checker: Checker,
+}
+
+impl Session {
+    pub fn check(&mut self, tree: &Tree) -> Vec<Diagnostic> {
+        self.checker.tree(tree);
+        std::mem::take(&mut self.checker.warnings)
+    }
+
+    /// The constructors known now, to go back to if a checked program's
+    /// types don't get declared after all
+    pub fn save(&self) -> Saved {
+        Saved(self.checker.siblings.clone())
+    }
+
+    pub fn restore(&mut self, saved: Saved) {
+        self.checker.siblings = saved.0;
+    }
+}
+
+/// What a [`Session`] rolls back to
+pub struct Saved(Constructors);
+
+#[derive(Default)]
+struct Checker {
+    /// The constructors of every type declared so far
+    siblings: Constructors,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn tree(&mut self, tree: &Tree) {
+        match tree {
+            Tree::Block { items, .. } => items.iter().for_each(|item| self.tree(item)),
+            // types are declared before their constructors can be used
+            Tree::Data(def) => decision::declare(&mut self.siblings, def),
+            Tree::Let { value, .. } => self.tree(value),
+            Tree::Fn { body, .. } => self.tree(body),
+            Tree::Match { scrutinee, arms, .. } => {
+                self.tree(scrutinee);
+                let mut rows: Vec<Row> = Vec::new();
+                for arm in arms {
+                    self.tree(&arm.body);
+
+                    let row = vec![lower(&arm.pattern)];
+                    if !self.useful(&rows, &row) {
+                        let diag = Diagnostic::warning("unreachable match arm")
+                            .with_code("W0201")
+                            .with_label(arm.pattern.context(), "earlier arms already match every value here");
+                        self.warnings.push(diag);
+                    }
+                    rows.push(row);
+                }
+
+                if let Some(witness) = self.missing(&rows, 1) {
+                    let diag = Diagnostic::warning("non-exhaustive match")
+                        .with_code("W0200")
+                        .with_label(scrutinee.context(), format!("pattern `{}` not covered", witness[0]))
+                        .with_help("add an arm for it, or a `_` arm at the end");
+                    self.warnings.push(diag);
+                }
+            }
+            Tree::If { cond, then, els, .. } => {
+                self.tree(cond);
+                self.tree(then);
+                self.tree(els);
+            }
+            Tree::Call { func, args, .. } => {
+                self.tree(func);
+                args.iter().for_each(|arg| self.tree(arg));
+            }
+            Tree::Binary { lhs, rhs, .. } => {
+                self.tree(lhs);
+                self.tree(rhs);
+            }
+            Tree::Unary { operand, .. } => self.tree(operand),
+            Tree::Var(_)
+            | Tree::IntLit { .. }
+            | Tree::BoolLit { .. }
+            | Tree::StrLit { .. }
+            | Tree::CharLit { .. }
+            | Tree::Error(_) => {}
+        }
+    }
+
+    /// The constructors in the first column with their arities, if they
+    /// cover the whole type; a constructor of no known type covers nothing
+    fn complete(&self, heads: &[Ctor]) -> Option<Vec<(Ctor, usize)>> {
+        let all = match heads.first()? {
+            Ctor::Cons(name) => self.siblings.get(name)?.iter().map(|&(c, arity)| (Ctor::Cons(c), arity)).collect(),
+            Ctor::Bool(_) => vec![(Ctor::Bool(false), 0), (Ctor::Bool(true), 0)],
+            // literals never cover their whole type
+            _ => return None,
+        };
+        all.iter().all(|(c, _): &(Ctor, usize)| heads.contains(c)).then_some(all)
+    }
+
+    /// `row` with its first pattern's fields spread out, if it could match `ctor`
+    fn specialize_row(&self, row: &Row, ctor: &Ctor, arity: usize) -> Option<Row> {
+        let mut fields = match &row[0] {
+            Pat::Wild => vec![Pat::Wild; arity],
+            Pat::Ctor(c, args) if c == ctor => args.clone(),
+            Pat::Ctor(..) => return None,
+        };
+        fields.extend_from_slice(&row[1..]);
+        Some(fields)
+    }
+
+    fn specialize(&self, rows: &[Row], ctor: &Ctor, arity: usize) -> Vec<Row> {
+        rows.iter().filter_map(|row| self.specialize_row(row, ctor, arity)).collect()
+    }
+
+    /// Rows whose first pattern matches anything, without it
+    fn default(&self, rows: &[Row]) -> Vec<Row> {
+        rows.iter()
+            .filter(|row| matches!(row[0], Pat::Wild))
+            .map(|row| row[1..].to_vec())
+            .collect()
+    }
+
+    fn heads(&self, rows: &[Row]) -> Vec<Ctor> {
+        let mut heads = Vec::new();
+        for row in rows {
+            if let Pat::Ctor(c, _) = &row[0] {
+                if !heads.contains(c) {
+                    heads.push(c.clone());
+                }
+            }
+        }
+        heads
+    }
+
+    /// Whether `row` matches some value no row in `rows` does
+    fn useful(&self, rows: &[Row], row: &Row) -> bool {
+        if row.is_empty() {
+            return rows.is_empty();
+        }
+
+        let useful = |ctor: &Ctor, arity| match self.specialize_row(row, ctor, arity) {
+            Some(row) => self.useful(&self.specialize(rows, ctor, arity), &row),
+            None => false,
+        };
+        match &row[0] {
+            Pat::Ctor(ctor, args) => useful(ctor, args.len()),
+            Pat::Wild => match self.complete(&self.heads(rows)) {
+                Some(all) => all.iter().any(|(ctor, arity)| useful(ctor, *arity)),
+                None => self.useful(&self.default(rows), &row[1..].to_vec()),
+            },
+        }
+    }
+
+    /// A row of `width` patterns matching a value that no row in `rows` does
+    fn missing(&self, rows: &[Row], width: usize) -> Option<Row> {
+        if width == 0 {
+            return rows.is_empty().then(Vec::new);
+        }
+
+        let heads = self.heads(rows);
+        if let Some(all) = self.complete(&heads) {
+            for (ctor, arity) in all {
+                if let Some(mut witness) = self.missing(&self.specialize(rows, &ctor, arity), arity + width - 1) {
+                    let rest = witness.split_off(arity);
+                    let mut row = vec![Pat::Ctor(ctor, witness)];
+                    row.extend(rest);
+                    return Some(row);
+                }
+            }
+            return None;
+        }
+
+        let mut witness = self.missing(&self.default(rows), width - 1)?;
+        let unused = |name| {
+            let all = self.siblings.get(name)?;
+            all.iter().find(|(c, _)| !heads.contains(&Ctor::Cons(c)))
+        };
+        let first = match heads.first() {
+            Some(Ctor::Cons(name)) => match unused(name) {
+                Some(&(cons, arity)) => Pat::Ctor(Ctor::Cons(cons), vec![Pat::Wild; arity]),
+                None => Pat::Wild,
+            },
+            Some(Ctor::Bool(b)) => Pat::Ctor(Ctor::Bool(!b), Vec::new()),
+            _ => Pat::Wild,
+        };
+        witness.insert(0, first);
+        Some(witness)
+    }
+}
@@ -0,0 +1,449 @@
+//! Prints the AST back out as source, in one canonical layout
+
+use std::sync::Arc;
+
+use crate::{
+    ast::s0::*,
+    context::Context,
+    diagnostic::Diagnostic,
+    lexer::{Token, TokenKind},
+    parser::{precedence, Assoc},
+    source::{self, FileId, SourceFile},
+};
+
+const INDENT: &str = "    ";
+
+/// The comments among `tokens`, as lexed with trivia
+pub fn comments(tokens: &[Token]) -> Vec<Context> {
+    tokens
+        .iter()
+        .filter(|token| token.token == TokenKind::Comment)
+        .map(|token| token.context)
+        .collect()
+}
+
+/// Formats a program, keeping its comments, and blank lines between
+/// top-level items
+pub fn format(tree: &Tree, comments: &[Context]) -> String {
+    let items = match tree {
+        Tree::Block { items, .. } => items.as_slice(),
+        tree => std::slice::from_ref(tree),
+    };
+
+    let mut printer = Printer {
+        file: source::get(tree.context().file),
+        comments,
+        next: 0,
+    };
+    let mut out = String::new();
+    // where the last thing printed ends in the source
+    let mut end = None;
+
+    for item in items {
+        let context = item.context();
+        for comment in printer.before(context.start) {
+            printer.blank_line(&mut out, end, comment.start);
+            out += &printer.text(comment);
+            out.push('\n');
+            end = Some(comment.start + comment.len);
+        }
+
+        printer.blank_line(&mut out, end, context.start);
+        out += &printer.item(item, 0);
+        end = Some(context.start + context.len);
+        for comment in printer.trailing(context) {
+            out += &format!(" {}", printer.text(comment));
+            end = Some(comment.start + comment.len);
+        }
+        out.push('\n');
+    }
+
+    for comment in printer.before(usize::MAX) {
+        printer.blank_line(&mut out, end, comment.start);
+        out += &printer.text(comment);
+        out.push('\n');
+        end = Some(comment.start + comment.len);
+    }
+    out
+}
+
+/// An error at the first line of `file` that formatting changes into
+/// `formatted`, unless it changes nothing
+pub fn unformatted(file: FileId, formatted: &str) -> Option<Diagnostic> {
+    let source = source::get(file);
+    if source.src == formatted {
+        return None;
+    }
+
+    let error = |context, label: String| {
+        Diagnostic::error(format!("{} is not formatted", source.name))
+            .with_label(context, label)
+            .with_help("`snd fmt` prints the program formatted")
+    };
+
+    let mut expected = formatted.split('\n');
+    let mut start = 0;
+    for line in source.src.split('\n') {
+        let context = Context {
+            file,
+            start,
+            len: line.trim_end_matches('\r').len(),
+        };
+        let label = match expected.next() {
+            Some(text) if text == line => {
+                start += line.len() + 1;
+                continue;
+            }
+            Some(text) => format!("formatted, this line is `{}`", text),
+            None => "formatting removes this line".to_string(),
+        };
+        return Some(error(context, label));
+    }
+
+    // every line is as formatted, so formatting adds to the end
+    let context = Context {
+        file,
+        start: source.src.len(),
+        len: 0,
+    };
+    Some(error(context, "formatting adds text here".to_string()))
+}
+
+fn indent(depth: usize) -> String {
+    INDENT.repeat(depth)
+}
+
+/// Formats expressions, placing comments before the items of blocks and the
+/// arms of matches, which each start a line
+///
+/// Comments anywhere else move to the next such place, since a line comment
+/// can't be put in the middle of a line.
+struct Printer<'a> {
+    file: Arc<SourceFile>,
+    /// Every comment in the source, in order
+    comments: &'a [Context],
+    /// The first comment not yet printed
+    next: usize,
+}
+
+impl Printer<'_> {
+    fn text(&self, context: Context) -> String {
+        self.file.src[context.start..context.start + context.len].trim_end().to_string()
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.file.line_col(offset).0
+    }
+
+    /// Takes the comments starting before `offset`
+    fn before(&mut self, offset: usize) -> Vec<Context> {
+        let start = self.next;
+        while self.next < self.comments.len() && self.comments[self.next].start < offset {
+            self.next += 1;
+        }
+        self.comments[start..self.next].to_vec()
+    }
+
+    /// Takes the comments that follow `context` on the line it ends on,
+    /// before any other token
+    fn trailing(&mut self, context: Context) -> Vec<Context> {
+        let mut end = context.start + context.len;
+        let start = self.next;
+        while let Some(&comment) = self.comments.get(self.next) {
+            // a parenthesized tree's context leaves out its `)`
+            if comment.start < end || !self.file.src[end..comment.start].chars().all(|c| matches!(c, ' ' | '\t' | ')')) {
+                break;
+            }
+            end = comment.start + comment.len;
+            self.next += 1;
+        }
+        self.comments[start..self.next].to_vec()
+    }
+
+    /// The comments trailing `context`, each after a space
+    fn after(&mut self, context: Context) -> String {
+        let mut out = String::new();
+        for comment in self.trailing(context) {
+            out += &format!(" {}", self.text(comment));
+        }
+        out
+    }
+
+    /// Whether `text` ends with a line comment, which what follows on the
+    /// same line would be part of
+    fn ends_with_line_comment(&self, text: &str) -> bool {
+        let Some(&last) = self.next.checked_sub(1).and_then(|i| self.comments.get(i)) else {
+            return false;
+        };
+        let comment = self.text(last);
+        (comment.starts_with("//") || comment.starts_with("--")) && text.ends_with(&comment)
+    }
+
+    /// Ends the line of `text` if it ends with a line comment, so that what
+    /// follows can go after it, at `depth`
+    fn close_line(&self, text: String, depth: usize) -> String {
+        match self.ends_with_line_comment(&text) {
+            true => format!("{}\n{}", text, indent(depth)),
+            false => text,
+        }
+    }
+
+    /// Keeps a blank line the source had between `end` and `start`
+    fn blank_line(&self, out: &mut String, end: Option<usize>, start: usize) {
+        if let Some(end) = end {
+            if self.line(start) > self.line(end.saturating_sub(1)) + 1 {
+                out.push('\n');
+            }
+        }
+    }
+
+    /// Comments before `offset`, each on its own line at `depth`
+    fn leading(&mut self, offset: usize, depth: usize) -> String {
+        let mut out = String::new();
+        for comment in self.before(offset) {
+            out += &format!("\n{}{}", indent(depth), self.text(comment));
+        }
+        out
+    }
+
+    /// An item in a block
+    fn item(&mut self, tree: &Tree, depth: usize) -> String {
+        match tree {
+            Tree::Data(def) => data(def),
+            Tree::Let { name, value, .. } => format!("let {} = {}", name, self.expr(value, depth)),
+            tree => self.expr(tree, depth),
+        }
+    }
+
+    /// An operand of `op`, on the left if `left`
+    fn operand(&mut self, tree: &Tree, op: BinOp, left: bool, depth: usize) -> String {
+        let text = self.expr(tree, depth);
+        let inner = match tree {
+            Tree::Binary { op, .. } => *op,
+            tree if open_ended(tree) => return parens(self.close_line(text, depth)),
+            _ => return text,
+        };
+
+        let (prec, assoc) = precedence(op);
+        let (inner_prec, _) = precedence(inner);
+        let grouped = inner_prec > prec
+            || inner_prec == prec
+                && match assoc {
+                    Assoc::Left => left,
+                    Assoc::Right => !left,
+                    Assoc::None => false,
+                };
+        if grouped {
+            text
+        } else {
+            parens(text)
+        }
+    }
+
+    fn expr(&mut self, tree: &Tree, depth: usize) -> String {
+        match tree {
+            Tree::Block { items, context } => {
+                let mut out = "{".to_string();
+                for item in items {
+                    out += &self.leading(item.context().start, depth + 1);
+                    out += &format!("\n{}{}", indent(depth + 1), self.item(item, depth + 1));
+                    out += &self.after(item.context());
+                }
+                out += &self.leading(context.start + context.len, depth + 1);
+
+                match out.as_str() {
+                    "{" => "{}".to_string(),
+                    _ => out + &format!("\n{}}}", indent(depth)),
+                }
+            }
+            Tree::Data(def) => data(def),
+            Tree::Let { name, value, .. } => format!("let {} = {}", name, self.expr(value, depth)),
+            Tree::Fn { params, body, .. } => {
+                let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                format!("fn({}) = {}", params.join(", "), self.expr(body, depth))
+            }
+            Tree::Match { scrutinee, arms, .. } => {
+                // the arms of a `match` in the scrutinee would take these
+                let text = self.expr(scrutinee, depth);
+                let mut out = match open_ended(scrutinee) {
+                    true => format!("match {}", parens(self.close_line(text, depth))),
+                    false => format!("match {}", text),
+                };
+                for arm in arms {
+                    out += &self.leading(arm.context.start, depth + 1);
+
+                    // a line in the arms' column would end them
+                    let body_depth = if else_line(&arm.body) { depth + 2 } else { depth + 1 };
+                    let body = self.expr(&arm.body, body_depth);
+                    out += &format!("\n{}| {} => {}", indent(depth + 1), pattern(&arm.pattern), body);
+                    out += &self.after(arm.context);
+                }
+                out
+            }
+            Tree::If { cond, then, els, context } => {
+                // blocks and `else if` chains line up with the `if`, other
+                // branches go on their own lines, indented, unless the whole
+                // `if` was on one line
+                let one_line = self.line(context.start) == self.line(context.start + context.len.saturating_sub(1));
+                let braced = matches!(**then, Tree::Block { .. });
+                let inline = matches!(**els, Tree::If { .. } | Tree::Block { .. });
+
+                let cond = self.expr(cond, depth);
+                let cond = self.close_line(cond, depth);
+                let then_text = self.expr(then, if braced { depth } else { depth + 1 });
+                let els_text = self.expr(els, if inline { depth } else { depth + 1 });
+
+                if one_line && !then_text.contains('\n') && !els_text.contains('\n') {
+                    return format!("if {} {} else {}", cond, then_text, els_text);
+                }
+
+                let mut out = match braced {
+                    true => format!("if {} {} ", cond, then_text),
+                    false => format!("if {}\n{}{}\n{}", cond, indent(depth + 1), then_text, indent(depth)),
+                };
+                match inline {
+                    true => out += &format!("else {}", els_text),
+                    false => out += &format!("else\n{}{}", indent(depth + 1), els_text),
+                }
+                out
+            }
+            Tree::Call { func, args, .. } => {
+                let callee = self.expr(func, depth);
+                let callee = match **func {
+                    Tree::Var(_) | Tree::Call { .. } => callee,
+                    _ => parens(self.close_line(callee, depth)),
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let text = self.expr(arg, depth);
+                        self.close_line(text, depth)
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}({})", callee, args.join(", "))
+            }
+            Tree::Binary { op, lhs, rhs, .. } => {
+                let lhs = self.operand(lhs, *op, true, depth);
+                let rhs = self.operand(rhs, *op, false, depth);
+                format!("{} {} {}", lhs, op, rhs)
+            }
+            Tree::Unary { op, operand, .. } => {
+                let text = self.expr(operand, depth);
+                match **operand {
+                    Tree::Binary { .. } => format!("{}{}", op, parens(text)),
+                    // `--` starts a comment
+                    _ if *op == UnOp::Neg && text.starts_with('-') => format!("{}{}", op, parens(text)),
+                    _ => format!("{}{}", op, text),
+                }
+            }
+            Tree::Var(name) => name.to_string(),
+            Tree::IntLit { value, .. } => value.to_string(),
+            Tree::BoolLit { value, .. } => value.to_string(),
+            Tree::StrLit { value, .. } => string(value),
+            Tree::CharLit { value, .. } => character(*value),
+            // kept as written, for lack of anything to lay out
+            Tree::Error(context) => self.text(*context),
+        }
+    }
+}
+
+fn data(def: &DataDef) -> String {
+    let mut out = format!("data {}", def.name);
+    for param in &def.params {
+        out += &format!(" {}", param);
+    }
+    out += " =";
+
+    for (i, cons) in def.cons.iter().enumerate() {
+        if i > 0 {
+            out += " |";
+        }
+        out += &format!(" {}", cons.name);
+        if !cons.fields.is_empty() {
+            let fields = cons.fields.iter().map(ty).collect::<Vec<_>>();
+            out += &format!("({})", fields.join(", "));
+        }
+    }
+    out
+}
+
+fn ty(ty: &Type) -> String {
+    match ty {
+        Type::Var(name) => name.to_string(),
+        Type::Named { name, args, .. } => {
+            let mut out = name.to_string();
+            for arg in args {
+                match arg {
+                    Type::Named { args, .. } if !args.is_empty() => out += &format!(" ({})", self::ty(arg)),
+                    arg => out += &format!(" {}", self::ty(arg)),
+                }
+            }
+            out
+        }
+    }
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Bind(name) => name.to_string(),
+        Pattern::Cons { name, args, .. } if args.is_empty() => name.to_string(),
+        Pattern::Cons { name, args, .. } => {
+            let args = args.iter().map(self::pattern).collect::<Vec<_>>();
+            format!("{}({})", name, args.join(", "))
+        }
+        Pattern::IntLit { value, .. } => value.to_string(),
+        Pattern::BoolLit { value, .. } => value.to_string(),
+        Pattern::StrLit { value, .. } => string(value),
+        Pattern::CharLit { value, .. } => character(*value),
+    }
+}
+
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c if c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// A string literal that reads back as `s`
+pub fn string(s: &str) -> String {
+    let body = s.chars().map(|c| escape(c, '"')).collect::<String>();
+    format!("\"{}\"", body)
+}
+
+/// A character literal that reads back as `c`
+pub fn character(c: char) -> String {
+    format!("'{}'", escape(c, '\''))
+}
+
+/// Whether the expression has no end of its own, so what follows it could
+/// be read as part of it
+fn open_ended(tree: &Tree) -> bool {
+    match tree {
+        Tree::Match { .. } | Tree::If { .. } | Tree::Fn { .. } => true,
+        Tree::Unary { operand, .. } => open_ended(operand),
+        _ => false,
+    }
+}
+
+/// Whether the expression may put `else` at the start of a line, at its own
+/// depth
+fn else_line(tree: &Tree) -> bool {
+    match tree {
+        Tree::If { .. } => true,
+        Tree::Fn { body, .. } => else_line(body),
+        Tree::Unary { operand, .. } => else_line(operand),
+        _ => false,
+    }
+}
+
+fn parens(text: String) -> String {
+    format!("({})", text)
+}
@@ -1,7 +1,13 @@
 use std::fmt::{self, Display, Formatter};
-use crate::{context::Context, util::{leak, Symbol}};
+use std::{iter::Peekable, rc::Rc, str::Chars, sync::Arc};
+use crate::{
+    context::Context,
+    diagnostic::Diagnostic,
+    source::{self, FileId, SourceFile},
+    util::Symbol,
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     Ident(&'static Symbol),
     Keyword(&'static str),
@@ -15,14 +21,41 @@ pub enum TokenKind {
     Comma,
 
     Pipe,
+    Equals,
     FatArrow,
 
+    // operators
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    EqEq,
+    NotEq,
+    AndAnd,
+    OrOr,
+    Bang,
+
     // literals
     IntLit(i64),
     BoolLit(bool),
+    StrLit(Rc<str>),
+    CharLit(char),
 
-    // whitespace, pruned
+    // trivia, pruned for the parser
     None,
+    Comment,
+
+    // layout, from the columns of lines starting with `|`; see `layout`
+    Indent,
+    Newline,
+    Dedent,
+
+    Eof,
 }
 
 impl TokenKind {
@@ -31,7 +64,8 @@ impl TokenKind {
         match self {
             Ident(s) => s.name.len(),
             Keyword(s) => s.len(),
-            FatArrow => 2,
+            FatArrow | Le | Ge | EqEq | NotEq | AndAnd | OrOr => 2,
+            Indent | Newline | Dedent | Eof => 0,
             BoolLit(b) => b.to_string().len(),
             IntLit(num) => num.to_string().len(),
             _ => 1,
@@ -39,12 +73,68 @@ impl TokenKind {
     }
 }
 
-#[derive(Debug)]
+/// How a token is described to users, e.g. "expected `)`, found keyword `let`"
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use TokenKind::*;
+        let symbol = match self {
+            Ident(s) => return write!(f, "identifier `{}`", s.name),
+            Keyword(k) => return write!(f, "keyword `{}`", k),
+            IntLit(n) => return write!(f, "integer `{}`", n),
+            BoolLit(b) => return write!(f, "`{}`", b),
+            StrLit(_) => return write!(f, "string literal"),
+            CharLit(_) => return write!(f, "character literal"),
+            None => return write!(f, "whitespace"),
+            Comment => return write!(f, "comment"),
+            Indent => return write!(f, "indent"),
+            Newline => return write!(f, "newline"),
+            Dedent => return write!(f, "dedent"),
+            Eof => return write!(f, "end of file"),
+            LParen => "(",
+            RParen => ")",
+            LBrace => "{",
+            RBrace => "}",
+            Colon => ":",
+            Comma => ",",
+            Pipe => "|",
+            Equals => "=",
+            FatArrow => "=>",
+            Plus => "+",
+            Minus => "-",
+            Star => "*",
+            Slash => "/",
+            Percent => "%",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            EqEq => "==",
+            NotEq => "!=",
+            AndAnd => "&&",
+            OrOr => "||",
+            Bang => "!",
+        };
+        write!(f, "`{}`", symbol)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token: TokenKind,
     pub context: Context,
 }
 
+impl Token {
+    /// Whether the token is whitespace or a comment, which the parser never sees
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.token, TokenKind::None | TokenKind::Comment)
+    }
+
+    pub fn is_layout(&self) -> bool {
+        matches!(self.token, TokenKind::Indent | TokenKind::Newline | TokenKind::Dedent)
+    }
+}
+
 impl PartialEq for Token {
     fn eq(&self, other: &Self) -> bool {
         self.token == other.token
@@ -57,112 +147,565 @@ impl Display for Token {
     }
 }
 
-fn is_keyword(s: &str) -> bool {
-    match s {
-        "fn" | "let" | "match" | "cond" | "itself" => true,
-        _ => false,
+#[derive(Debug)]
+pub enum LexErrorKind {
+    UnterminatedComment,
+    UnterminatedString,
+    UnterminatedChar,
+    EmptyChar,
+    OverlongChar,
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
+    InvalidChar(char),
+    IntegerOverflow,
+    UnreadableFile(String),
+    /// A `|` starting a line left of the `|` lines above it, in the column
+    /// given, and right of any further out
+    MisalignedPipe(usize),
+}
+
+#[derive(Debug)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub context: Context,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use LexErrorKind::*;
+        match self.kind {
+            UnterminatedComment => write!(f, "unterminated block comment"),
+            UnterminatedString => write!(f, "unterminated string literal"),
+            UnterminatedChar => write!(f, "unterminated character literal"),
+            EmptyChar => write!(f, "empty character literal"),
+            OverlongChar => write!(f, "character literal may only contain one character"),
+            InvalidEscape(c) => write!(f, "unknown escape sequence `\\{}`", c.escape_default()),
+            InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            InvalidChar(c) => write!(f, "unexpected character `{}`", c.escape_default()),
+            IntegerOverflow => write!(f, "integer literal is too large"),
+            UnreadableFile(ref err) => write!(f, "could not read {}: {}", self.context.path(), err),
+            MisalignedPipe(_) => write!(f, "`|` does not line up with the `|` lines above it"),
+        }
+    }
+}
+
+impl LexError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        use LexErrorKind::*;
+        let diag = Diagnostic::error(self.to_string());
+        let context = self.context;
+
+        match self.kind {
+            UnterminatedComment => diag
+                .with_code("E0001")
+                .with_label(context, "comment starts here")
+                .with_note("block comments nest, so each `/*` needs its own `*/`"),
+            UnterminatedString => diag
+                .with_code("E0002")
+                .with_label(context, "string starts here"),
+            UnterminatedChar => diag
+                .with_code("E0003")
+                .with_label(context, "character literal starts here"),
+            EmptyChar => diag.with_code("E0004").with_label(context, ""),
+            OverlongChar => diag
+                .with_code("E0005")
+                .with_label(context, "")
+                .with_help("use double quotes for a string"),
+            InvalidEscape(_) => diag
+                .with_code("E0006")
+                .with_label(context, "unknown escape")
+                .with_note("valid escapes are \\n \\t \\r \\0 \\\\ \\\" \\' and \\u{...}"),
+            InvalidUnicodeEscape => diag
+                .with_code("E0007")
+                .with_label(context, "")
+                .with_help("write `\\u{...}` with 1 to 6 hex digits naming a unicode scalar value"),
+            InvalidChar(_) => diag
+                .with_code("E0008")
+                .with_label(context, "not valid in snd source"),
+            IntegerOverflow => diag
+                .with_code("E0009")
+                .with_label(context, "")
+                .with_note(format!("the largest integer is {}", i64::MAX)),
+            UnreadableFile(_) => diag.with_code("E0010"),
+            MisalignedPipe(column) => diag
+                .with_code("E0011")
+                .with_label(context, "")
+                .with_note(format!("the `|` lines above it start in column {}", column))
+                .with_help("line it up with the arms it belongs to, or indent it further to start arms of its own"),
+        }
     }
 }
 
+const KEYWORDS: &[&str] = &["fn", "let", "match", "cond", "itself", "data", "if", "else"];
+
+fn keyword(s: &str) -> Option<&'static str> {
+    KEYWORDS.iter().find(|&&k| k == s).copied()
+}
+
 fn is_int(s: &str) -> bool {
-    s.chars().all(|c| c.is_digit(10))
+    s.chars().all(|c| c.is_ascii_digit())
 }
 
 fn is_bool(s: &str) -> bool {
     s == "true" || s == "false"
 }
 
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
 pub struct Lexer {
-    path: &'static str,
-    src: &'static str,
+    file: FileId,
+    source: Arc<SourceFile>,
 
     pos: usize,
     accum: String,
 
     tokens: Vec<Token>,
+    errors: Vec<LexError>,
+}
+
+/// Reads a source file, failing with an error that names it
+pub fn read(path: &str) -> Result<String, LexError> {
+    std::fs::read_to_string(path).map_err(|err| LexError {
+        kind: LexErrorKind::UnreadableFile(err.to_string()),
+        context: Context {
+            // an empty file, just so the error can name the path
+            file: source::add(path, ""),
+            start: 0,
+            len: 0,
+        },
+    })
 }
 
 impl Lexer {
-    pub fn new(path: &str) -> Self {
-        let src = std::fs::read_to_string(path).expect("could not read file");
+    pub fn new(path: &str) -> Result<Self, LexError> {
+        Ok(Self::from_source(path, &read(path)?))
+    }
+
+    /// Lexes `src` directly, with `name` standing in for a path in contexts,
+    /// e.g. `<repl>` or `<stdin>`
+    pub fn from_source(name: &str, src: &str) -> Self {
+        let file = source::add(name, src);
 
         Self {
-            path: leak(path),
-            src: leak(&src),
+            file,
+            source: source::get(file),
             pos: 0,
             accum: String::new(),
             tokens: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
     fn push_accum(&mut self) {
-        if !self.accum.is_empty() {
-            let text = leak(&self.accum);
-
-            let token = match text {
-                _ if is_keyword(text) => TokenKind::Keyword(text),
-                _ if is_int(text) => TokenKind::IntLit(text.parse().unwrap()),
-                _ if is_bool(text) => TokenKind::BoolLit(text == "true"),
-                _ => TokenKind::Ident(Symbol::new(text)),
-            };
-
-            self.accum.clear();
-            self.push(token);
+        if self.accum.is_empty() {
+            return;
         }
+
+        let text = std::mem::take(&mut self.accum);
+        let text = text.as_str();
+        let len = text.len();
+
+        let token = match keyword(text) {
+            Some(keyword) => TokenKind::Keyword(keyword),
+            None if is_int(text) => match text.parse() {
+                Ok(value) => TokenKind::IntLit(value),
+                Err(_) => {
+                    self.error(LexErrorKind::IntegerOverflow, self.pos, len);
+                    TokenKind::IntLit(0)
+                }
+            },
+            None if is_bool(text) => TokenKind::BoolLit(text == "true"),
+            None => TokenKind::Ident(Symbol::new(text)),
+        };
+
+        self.push_len(token, len);
     }
 
     fn push(&mut self, token: TokenKind) {
-        self.push_accum();
         let len = token.length();
+        self.push_len(token, len);
+    }
+
+    /// Pushes a token whose source text isn't derivable from the token itself
+    fn push_len(&mut self, token: TokenKind, len: usize) {
+        self.push_accum();
 
         self.tokens.push(Token {
             token,
-            context: Context {
-                start: self.pos,
-                len,
-
-                path: self.path,
-                src: self.src,
-            },
+            context: self.context(self.pos, len),
         });
 
         self.pos += len;
     }
 
-    pub fn lex(mut self) -> Vec<Token> {
-        let mut chars = self.src.chars();
+    fn context(&self, start: usize, len: usize) -> Context {
+        Context {
+            file: self.file,
+            start,
+            len,
+        }
+    }
+
+    /// Moves past source text that doesn't produce a token
+    fn skip(&mut self, len: usize) {
+        self.push_accum();
+        self.pos += len;
+    }
+
+    /// Lexes a `//` or `--` comment up to, but not including, the newline
+    fn line_comment(&mut self, chars: &mut Peekable<Chars>) {
+        let mut len = 2;
+        while let Some(c) = chars.next_if(|&c| c != '\n') {
+            len += c.len_utf8();
+        }
+        self.push_len(TokenKind::Comment, len);
+    }
+
+    /// Lexes a `/* */` comment, which may nest
+    fn block_comment(&mut self, chars: &mut Peekable<Chars>) {
+        self.push_accum();
+        let start = self.pos;
+
+        let mut len = 2;
+        let mut depth = 1;
+
+        while depth > 0 {
+            let Some(c) = chars.next() else {
+                self.errors.push(LexError {
+                    kind: LexErrorKind::UnterminatedComment,
+                    context: self.context(start, 2),
+                });
+                break;
+            };
+            len += c.len_utf8();
+
+            match c {
+                '/' if chars.next_if_eq(&'*').is_some() => {
+                    len += 1;
+                    depth += 1;
+                }
+                '*' if chars.next_if_eq(&'/').is_some() => {
+                    len += 1;
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        self.push_len(TokenKind::Comment, len);
+    }
+
+    fn error(&mut self, kind: LexErrorKind, start: usize, len: usize) {
+        let context = self.context(start, len);
+        self.errors.push(LexError { kind, context });
+    }
+
+    /// Reads the escape sequence after a backslash, `len` being the length of
+    /// the literal so far, backslash included
+    fn escape(&mut self, chars: &mut Peekable<Chars>, len: &mut usize) -> Option<char> {
+        // unterminated literals are reported by the caller
+        let c = *chars.peek()?;
+        let at = self.pos + *len;
+
+        let escaped = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' | '\'' => c,
+            'u' => {
+                chars.next();
+                *len += 1;
+                return self.unicode_escape(chars, len);
+            }
+            '\n' => {
+                // left in place, so the newline still ends a character literal
+                self.error(LexErrorKind::InvalidEscape(c), at, 0);
+                return None;
+            }
+            _ => {
+                chars.next();
+                *len += c.len_utf8();
+                self.error(LexErrorKind::InvalidEscape(c), at, c.len_utf8());
+                return None;
+            }
+        };
+
+        chars.next();
+        *len += 1;
+        Some(escaped)
+    }
+
+    fn unicode_escape(&mut self, chars: &mut Peekable<Chars>, len: &mut usize) -> Option<char> {
+        let start = self.pos + *len - 2;
+
+        if chars.next_if_eq(&'{').is_none() {
+            let bad = chars.peek().map_or(0, |c| c.len_utf8());
+            self.error(LexErrorKind::InvalidUnicodeEscape, self.pos + *len, bad);
+            return None;
+        }
+        *len += 1;
+
+        let mut value = 0;
+        let mut digits = 0;
+
+        loop {
+            match chars.peek() {
+                Some('}') => break,
+                Some(c) if c.is_ascii_hexdigit() && digits < 6 => {
+                    value = value * 16 + c.to_digit(16).unwrap();
+                    digits += 1;
+                }
+                c => {
+                    let bad = c.map_or(0, |c| c.len_utf8());
+                    self.error(LexErrorKind::InvalidUnicodeEscape, self.pos + *len, bad);
+                    return None;
+                }
+            }
+
+            chars.next();
+            *len += 1;
+        }
+
+        chars.next();
+        *len += 1;
+
+        let c = char::from_u32(value).filter(|_| digits > 0);
+        if c.is_none() {
+            self.error(LexErrorKind::InvalidUnicodeEscape, start, self.pos + *len - start);
+        }
+        c
+    }
+
+    /// Lexes a string literal after its opening quote, which may span lines
+    fn string(&mut self, chars: &mut Peekable<Chars>) {
+        self.push_accum();
+
+        let mut len = 1;
+        let mut text = String::new();
+
+        loop {
+            let Some(c) = chars.next() else {
+                self.error(LexErrorKind::UnterminatedString, self.pos, 1);
+                break;
+            };
+            len += c.len_utf8();
+
+            match c {
+                '"' => break,
+                '\\' => text.extend(self.escape(chars, &mut len)),
+                c => text.push(c),
+            }
+        }
+
+        self.push_len(TokenKind::StrLit(text.into()), len);
+    }
+
+    /// Lexes a character literal after its opening quote
+    fn character(&mut self, chars: &mut Peekable<Chars>) {
+        self.push_accum();
+
+        let mut len = 1;
+        let mut value = None;
+        let mut count = 0;
+        let errors = self.errors.len();
+
+        loop {
+            let Some(c) = chars.next_if(|&c| c != '\n') else {
+                self.error(LexErrorKind::UnterminatedChar, self.pos, 1);
+                break;
+            };
+            len += c.len_utf8();
+
+            let c = match c {
+                '\'' => break,
+                '\\' => self.escape(chars, &mut len),
+                c => Some(c),
+            };
+
+            count += 1;
+            value = value.or(c);
+        }
+
+        // a malformed escape already explains what's wrong with the literal
+        if self.errors.len() == errors {
+            match count {
+                0 => self.error(LexErrorKind::EmptyChar, self.pos, len),
+                1 => {}
+                _ => self.error(LexErrorKind::OverlongChar, self.pos, len),
+            }
+        }
+
+        self.push_len(TokenKind::CharLit(value.unwrap_or('\0')), len);
+    }
+
+    /// The source being lexed, in the global source map
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    /// Lexes for the parser: without trivia, and with layout
+    pub fn lex(self) -> Result<Vec<Token>, Vec<LexError>> {
+        let tokens = self.lex_with_trivia()?;
+        layout(tokens.into_iter().filter(|t| !t.is_trivia()).collect())
+    }
+
+    /// Lexes keeping whitespace and comments, so the tokens cover the source
+    pub fn lex_with_trivia(mut self) -> Result<Vec<Token>, Vec<LexError>> {
+        let source = self.source.clone();
+        let mut chars = source.src.chars().peekable();
+
+        // picks the two-character operator if `next` follows
+        fn two(chars: &mut Peekable<Chars>, next: char, long: TokenKind, short: TokenKind) -> TokenKind {
+            if chars.next_if_eq(&next).is_some() {
+                long
+            } else {
+                short
+            }
+        }
 
         while let Some(c) = chars.next() {
             match c {
-                c if c.is_whitespace() => self.push(TokenKind::None),
+                c if c.is_whitespace() => self.push_len(TokenKind::None, c.len_utf8()),
                 '(' => self.push(TokenKind::LParen),
                 ')' => self.push(TokenKind::RParen),
                 '{' => self.push(TokenKind::LBrace),
                 '}' => self.push(TokenKind::RBrace),
                 ':' => self.push(TokenKind::Colon),
                 ',' => self.push(TokenKind::Comma),
-                '|' => self.push(TokenKind::Pipe),
-                '=' => match chars.next() {
+                '|' => self.push(two(&mut chars, '|', TokenKind::OrOr, TokenKind::Pipe)),
+                '+' => self.push(TokenKind::Plus),
+                // so `1 --2` is `1`, where `1 - -2` is 3
+                '-' if chars.next_if_eq(&'-').is_some() => self.line_comment(&mut chars),
+                '-' => self.push(TokenKind::Minus),
+                '*' => self.push(TokenKind::Star),
+                '/' => match chars.next_if(|&c| c == '/' || c == '*') {
+                    Some('/') => self.line_comment(&mut chars),
+                    Some(_) => self.block_comment(&mut chars),
+                    None => self.push(TokenKind::Slash),
+                },
+                '%' => self.push(TokenKind::Percent),
+                '<' => self.push(two(&mut chars, '=', TokenKind::Le, TokenKind::Lt)),
+                '>' => self.push(two(&mut chars, '=', TokenKind::Ge, TokenKind::Gt)),
+                '!' => self.push(two(&mut chars, '=', TokenKind::NotEq, TokenKind::Bang)),
+                '=' => match chars.next_if(|&c| c == '>' || c == '=') {
                     Some('>') => self.push(TokenKind::FatArrow),
-                    Some(' ') => {
-                        self.accum.push('=');
-                        self.push(TokenKind::None);
-                    }
-                    Some(o) => {
-                        self.accum.push('=');
-                        self.accum.push(o);
-                    }
-                    None => self.push(TokenKind::None),
+                    Some(_) => self.push(TokenKind::EqEq),
+                    None => self.push(TokenKind::Equals),
                 },
+                '"' => self.string(&mut chars),
+                '\'' => self.character(&mut chars),
+                '&' if chars.next_if_eq(&'&').is_some() => self.push(TokenKind::AndAnd),
+                c if is_ident_char(c) => self.accum.push(c),
                 c => {
-                    self.accum.push(c);
+                    self.push_accum();
+                    self.error(LexErrorKind::InvalidChar(c), self.pos, c.len_utf8());
+                    self.skip(c.len_utf8());
                 }
             }
         }
 
-        self.tokens
-            .into_iter()
-            .filter(|t| t.token != TokenKind::None)
-            .collect()
+        self.push(TokenKind::Eof);
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        Ok(self.tokens)
+    }
+}
+
+/// Adds layout tokens to `tokens`, which have no trivia, so that lines of
+/// `|` can be told apart by their indentation
+///
+/// A `|` starting a line right of the `|` lines around it opens a block of
+/// them, with an `Indent`; those after it in its column are each preceded by
+/// a `Newline`, and a `Dedent` closes the block before the first line
+/// starting further left, or before any other line starting in its column.
+/// A bracket closes the blocks opened inside it, and columns are only
+/// compared between lines at the same depth of brackets.
+pub fn layout(tokens: Vec<Token>) -> Result<Vec<Token>, Vec<LexError>> {
+    let Some(first) = tokens.first() else {
+        return Ok(tokens);
+    };
+    let source = source::get(first.context.file);
+
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut errors = Vec::new();
+    // the column and bracket depth of each open block, innermost last
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    let mut depth = 0usize;
+    let mut line = 0;
+
+    for token in tokens {
+        let (token_line, column) = source.line_col(token.context.start);
+        let starts_line = token_line != line;
+        line = token_line;
+
+        let layout = |kind| Token {
+            token: kind,
+            context: Context {
+                len: 0,
+                ..token.context
+            },
+        };
+        // closes the blocks that `open` says are over
+        let mut close = |out: &mut Vec<Token>, open: &dyn Fn(usize, usize) -> bool| {
+            let mut closed = None;
+            while let Some(&(block_column, block_depth)) = blocks.last() {
+                if open(block_column, block_depth) {
+                    break;
+                }
+                blocks.pop();
+                out.push(layout(TokenKind::Dedent));
+                closed = Some(block_column);
+            }
+            closed
+        };
+
+        match token.token {
+            TokenKind::Eof => {
+                close(&mut out, &|_, _| false);
+            }
+            TokenKind::RParen | TokenKind::RBrace => {
+                depth = depth.saturating_sub(1);
+                close(&mut out, &|_, block_depth| block_depth <= depth);
+            }
+            TokenKind::Pipe if starts_line => {
+                let closed = close(&mut out, &|block_column, block_depth| block_depth != depth || block_column <= column);
+                match blocks.last() {
+                    Some(&(block_column, block_depth)) if block_depth == depth && block_column == column => {
+                        out.push(layout(TokenKind::Newline));
+                    }
+                    _ => {
+                        if let Some(closed) = closed {
+                            errors.push(LexError {
+                                kind: LexErrorKind::MisalignedPipe(closed),
+                                context: token.context,
+                            });
+                        }
+                        out.push(layout(TokenKind::Indent));
+                        blocks.push((column, depth));
+                    }
+                }
+            }
+            _ if starts_line => {
+                close(&mut out, &|block_column, block_depth| block_depth != depth || block_column < column);
+            }
+            _ => {}
+        }
+
+        if matches!(token.token, TokenKind::LParen | TokenKind::LBrace) {
+            depth += 1;
+        }
+        out.push(token);
+    }
+
+    if errors.is_empty() {
+        Ok(out)
+    } else {
+        Err(errors)
     }
 }
@@ -0,0 +1,26 @@
+//! The snd language: a lexer, parser, type checker and bytecode VM, with a
+//! tree-walking evaluator to check the VM against.
+//!
+//! [`Engine`] is the embedding API: it loads programs from source and calls
+//! their top-level functions with [`Value`]s. The modules below are the
+//! pipeline it is built from, used by the `snd` command line tool.
+
+pub mod util;
+pub mod ast;
+pub mod cst;
+pub mod lexer;
+pub mod parser;
+pub mod context;
+pub mod source;
+pub mod diagnostic;
+pub mod json;
+pub mod vm;
+pub mod eval;
+pub mod types;
+pub mod exhaustive;
+pub mod decision;
+pub mod fmt;
+pub mod lsp;
+mod engine;
+
+pub use engine::{Engine, Error, ErrorKind, Loaded, Value};
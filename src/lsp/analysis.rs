@@ -0,0 +1,389 @@
+//! What the server knows about one open document
+
+use crate::{
+    ast::s0::{self, DataDef, Ident, Pattern, Tree},
+    context::Context,
+    diagnostic::{Diagnostic, Severity},
+    exhaustive,
+    json::Json,
+    lexer::{LexError, Lexer},
+    parser::{ParseError, Parser},
+    source::{self, FileId},
+    types,
+    util::Symbol,
+};
+
+pub struct Analysis {
+    pub uri: String,
+    file: FileId,
+    tree: Option<Tree>,
+    diagnostics: Vec<Diagnostic>,
+    /// Every use of a name, with the name it refers to
+    references: Vec<(Context, Context)>,
+    /// What hovering over a name shows
+    hovers: Vec<(Context, String)>,
+    /// The types of top-level definitions, shown with their symbols
+    details: Vec<(Context, String)>,
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        source::remove(self.file);
+    }
+}
+
+impl Analysis {
+    /// Checks a document as far as it gets
+    pub fn new(uri: &str, src: &str) -> Analysis {
+        let lexer = Lexer::from_source(uri, src);
+        let mut analysis = Analysis {
+            uri: uri.to_string(),
+            file: lexer.file(),
+            tree: None,
+            diagnostics: Vec::new(),
+            references: Vec::new(),
+            hovers: Vec::new(),
+            details: Vec::new(),
+        };
+
+        let tokens = match lexer.lex() {
+            Ok(tokens) => tokens,
+            Err(errors) => {
+                analysis.diagnostics = errors.iter().map(LexError::diagnostic).collect();
+                return analysis;
+            }
+        };
+
+        // names in what could be parsed can still be followed
+        let mut parser = Parser::new(tokens);
+        let (tree, errors) = parser.parse_partial();
+        analysis.diagnostics.extend(parser.warnings());
+        analysis.diagnostics.extend(errors.iter().map(ParseError::diagnostic));
+
+        Resolver {
+            analysis: &mut analysis,
+            values: Vec::new(),
+            types: Vec::new(),
+        }
+        .tree(&tree);
+
+        if !errors.is_empty() {
+            analysis.tree = Some(tree);
+            return analysis;
+        }
+        match types::check(&tree) {
+            Ok(typed) => {
+                for (name, scheme) in &typed.bindings {
+                    let text = format!("{} : {}", name, scheme);
+                    analysis.hovers.push((name.context, text.clone()));
+                    analysis.details.push((name.context, scheme.to_string()));
+                }
+                let file = source::get(analysis.file);
+                for (context, ty) in &typed.names {
+                    let name = &file.src[context.start..context.start + context.len];
+                    let text = format!("{} : {}", name, ty);
+                    analysis.hovers.push((*context, text));
+                }
+                analysis.diagnostics.extend(exhaustive::check(&tree));
+            }
+            Err(errors) => analysis
+                .diagnostics
+                .extend(errors.iter().map(types::TypeError::diagnostic)),
+        }
+
+        analysis.tree = Some(tree);
+        analysis
+    }
+
+    /// The name defining what's at `offset`
+    pub fn definition(&self, offset: usize) -> Option<Context> {
+        innermost(&self.references, offset).copied()
+    }
+
+    /// What's at `offset`, and its type
+    pub fn hover(&self, offset: usize) -> Option<(Context, &str)> {
+        let (context, text) = self
+            .hovers
+            .iter()
+            .filter(|(context, _)| contains(context, offset))
+            .min_by_key(|(context, _)| context.len)?;
+        Some((*context, text))
+    }
+
+    /// The byte offset of an LSP position, clamped to the document
+    pub fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+
+        let file = source::get(self.file);
+        if line >= file.line_count() {
+            return Some(file.src.len());
+        }
+        let start = file.line_start(line + 1);
+        let mut units = 0;
+        for (i, c) in file.line(line + 1).char_indices() {
+            if units >= character {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(start + file.line(line + 1).len())
+    }
+
+    /// An LSP position: a 0-based line, and a column in UTF-16 code units
+    fn position(&self, offset: usize) -> Json {
+        let file = source::get(self.file);
+        let (line, _) = file.line_col(offset);
+        let start = file.line_start(line);
+        let character = file.src[start..offset].encode_utf16().count();
+        Json::object([("line", (line - 1).into()), ("character", character.into())])
+    }
+
+    pub fn range(&self, context: Context) -> Json {
+        Json::object([
+            ("start", self.position(context.start)),
+            ("end", self.position(context.start + context.len)),
+        ])
+    }
+
+    pub fn location(&self, context: Context) -> Json {
+        Json::object([("uri", self.uri.as_str().into()), ("range", self.range(context))])
+    }
+
+    pub fn diagnostics(&self) -> Json {
+        let diagnostics = self.diagnostics.iter().map(|diag| self.diagnostic(diag)).collect::<Vec<_>>();
+        diagnostics.into()
+    }
+
+    fn diagnostic(&self, diag: &Diagnostic) -> Json {
+        let context = diag.primary().unwrap_or(Context {
+            file: self.file,
+            start: 0,
+            len: 0,
+        });
+
+        // labels and notes go with the message, as editors show one text
+        let mut message = diag.message.clone();
+        for label in diag.labels.iter().filter(|label| label.primary && !label.message.is_empty()) {
+            message += &format!("\n{}", label.message);
+        }
+        for note in &diag.notes {
+            message += &format!("\nnote: {}", note);
+        }
+        for help in &diag.help {
+            message += &format!("\nhelp: {}", help);
+        }
+
+        let related = diag
+            .labels
+            .iter()
+            .filter(|label| !label.primary)
+            .map(|label| {
+                Json::object([
+                    ("location", self.location(label.context)),
+                    ("message", label.message.as_str().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let severity: usize = match diag.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        Json::object([
+            ("range", self.range(context)),
+            ("severity", severity.into()),
+            ("code", diag.code.into()),
+            ("source", "snd".into()),
+            ("message", message.into()),
+            ("relatedInformation", related.into()),
+        ])
+    }
+
+    /// The document's top-level definitions, with data constructors under
+    /// their types
+    pub fn symbols(&self) -> Json {
+        let items = match &self.tree {
+            Some(Tree::Block { items, .. }) => items.as_slice(),
+            Some(tree) => std::slice::from_ref(tree),
+            None => &[],
+        };
+
+        let mut symbols = Vec::new();
+        for item in items {
+            match item {
+                Tree::Let { name, value, context } => {
+                    let kind = match **value {
+                        Tree::Fn { .. } => SYMBOL_FUNCTION,
+                        _ => SYMBOL_VARIABLE,
+                    };
+                    let detail = self.details.iter().find(|(c, _)| *c == name.context);
+                    symbols.push(self.symbol(*name, kind, *context, detail.map(|(_, d)| d.as_str()), Vec::new()));
+                }
+                Tree::Data(def) => {
+                    let children = def
+                        .cons
+                        .iter()
+                        .map(|cons| self.symbol(cons.name, SYMBOL_ENUM_MEMBER, cons.context, None, Vec::new()))
+                        .collect();
+                    symbols.push(self.symbol(def.name, SYMBOL_ENUM, def.context, None, children));
+                }
+                _ => {}
+            }
+        }
+        symbols.into()
+    }
+
+    fn symbol(&self, name: Ident, kind: usize, context: Context, detail: Option<&str>, children: Vec<Json>) -> Json {
+        Json::object([
+            ("name", name.name.name.into()),
+            ("detail", detail.into()),
+            ("kind", kind.into()),
+            ("range", self.range(context)),
+            ("selectionRange", self.range(name.context)),
+            ("children", children.into()),
+        ])
+    }
+}
+
+const SYMBOL_ENUM: usize = 10;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+const SYMBOL_ENUM_MEMBER: usize = 22;
+
+fn contains(context: &Context, offset: usize) -> bool {
+    context.start <= offset && offset <= context.start + context.len
+}
+
+/// The value of the smallest span containing `offset`
+fn innermost<T>(spans: &[(Context, T)], offset: usize) -> Option<&T> {
+    spans
+        .iter()
+        .filter(|(context, _)| contains(context, offset))
+        .min_by_key(|(context, _)| context.len)
+        .map(|(_, value)| value)
+}
+
+/// Finds what each name refers to, following the type checker's scoping
+struct Resolver<'a> {
+    analysis: &'a mut Analysis,
+    /// Values in scope, innermost last
+    values: Vec<(&'static Symbol, Context)>,
+    /// Types and type parameters in scope
+    types: Vec<(&'static Symbol, Context)>,
+}
+
+impl Resolver<'_> {
+    fn bind(&mut self, name: Ident) {
+        self.values.push((name.name, name.context));
+    }
+
+    fn value(&mut self, name: Ident) {
+        if let Some((_, def)) = self.values.iter().rev().find(|(n, _)| *n == name.name) {
+            self.analysis.references.push((name.context, *def));
+        }
+    }
+
+    fn data(&mut self, def: &DataDef) {
+        self.types.push((def.name.name, def.name.context));
+        let scope = self.types.len();
+        for param in &def.params {
+            self.types.push((param.name, param.context));
+        }
+        for cons in &def.cons {
+            for field in &cons.fields {
+                self.ty(field);
+            }
+        }
+        self.types.truncate(scope);
+
+        for cons in &def.cons {
+            self.bind(cons.name);
+        }
+    }
+
+    fn ty(&mut self, ty: &s0::Type) {
+        let (name, args) = match ty {
+            s0::Type::Var(name) => (name, &[][..]),
+            s0::Type::Named { name, args, .. } => (name, args.as_slice()),
+        };
+        if let Some((_, def)) = self.types.iter().rev().find(|(n, _)| *n == name.name) {
+            self.analysis.references.push((name.context, *def));
+        }
+        for arg in args {
+            self.ty(arg);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Bind(name) => self.bind(*name),
+            Pattern::Cons { name, args, .. } => {
+                self.value(*name);
+                for arg in args {
+                    self.pattern(arg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tree(&mut self, tree: &Tree) {
+        match tree {
+            Tree::Block { items, .. } => {
+                let scope = (self.values.len(), self.types.len());
+                for item in items {
+                    self.tree(item);
+                }
+                self.values.truncate(scope.0);
+                self.types.truncate(scope.1);
+            }
+            Tree::Data(def) => self.data(def),
+            Tree::Let { name, value, .. } => {
+                // functions may call themselves
+                if matches!(**value, Tree::Fn { .. }) {
+                    self.bind(*name);
+                    self.tree(value);
+                } else {
+                    self.tree(value);
+                    self.bind(*name);
+                }
+            }
+            Tree::Fn { params, body, .. } => {
+                let scope = self.values.len();
+                for param in params {
+                    self.bind(*param);
+                }
+                self.tree(body);
+                self.values.truncate(scope);
+            }
+            Tree::Match { scrutinee, arms, .. } => {
+                self.tree(scrutinee);
+                for arm in arms {
+                    let scope = self.values.len();
+                    self.pattern(&arm.pattern);
+                    self.tree(&arm.body);
+                    self.values.truncate(scope);
+                }
+            }
+            Tree::If { cond, then, els, .. } => {
+                self.tree(cond);
+                self.tree(then);
+                self.tree(els);
+            }
+            Tree::Call { func, args, .. } => {
+                self.tree(func);
+                for arg in args {
+                    self.tree(arg);
+                }
+            }
+            Tree::Binary { lhs, rhs, .. } => {
+                self.tree(lhs);
+                self.tree(rhs);
+            }
+            Tree::Unary { operand, .. } => self.tree(operand),
+            Tree::Var(name) => self.value(*name),
+            Tree::IntLit { .. } | Tree::BoolLit { .. } | Tree::StrLit { .. } | Tree::CharLit { .. } | Tree::Error(_) => {}
+        }
+    }
+}
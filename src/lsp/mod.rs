@@ -0,0 +1,243 @@
+//! `snd lsp`: a language server speaking JSON-RPC over stdin and stdout
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::json::Json;
+
+use analysis::Analysis;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Text document sync kind: documents are sent whole on every change
+const SYNC_FULL: usize = 1;
+
+/// Serves until the client says to exit, returning whether it asked to shut
+/// down first, as a clean exit requires
+pub fn serve(input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server {
+        input,
+        output,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    server.run()?;
+    Ok(server.shutdown)
+}
+
+struct Server<R, W> {
+    input: R,
+    output: W,
+    documents: HashMap<String, Analysis>,
+    shutdown: bool,
+}
+
+/// A reply to a request, or why it failed
+type Reply = Result<Json, (i64, String)>;
+
+fn invalid_params() -> (i64, String) {
+    (INVALID_PARAMS, "invalid params".to_string())
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(body) = self.read()? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(err) => {
+                    self.reply(Json::Null, Err((PARSE_ERROR, err)))?;
+                    continue;
+                }
+            };
+
+            let Some(method) = message.get("method").and_then(Json::as_str) else {
+                // replies to requests from the server, which it never makes
+                if message.get("id").is_none() {
+                    self.reply(Json::Null, Err((INVALID_REQUEST, "no method".to_string())))?;
+                }
+                continue;
+            };
+            let params = message.get("params").unwrap_or(&Json::Null);
+
+            match message.get("id") {
+                Some(id) => {
+                    let reply = self.request(method, params);
+                    self.reply(id.clone(), reply)?;
+                }
+                None if method == "exit" => return Ok(()),
+                None => self.notification(method, params)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a message's body, or `None` at the end of the input
+    fn read(&mut self) -> io::Result<Option<String>> {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let Some(length) = length else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"));
+        };
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        String::from_utf8(body)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn reply(&mut self, id: Json, reply: Reply) -> io::Result<()> {
+        let outcome = match reply {
+            Ok(result) => ("result", result),
+            Err((code, message)) => (
+                "error",
+                Json::object([("code", code.into()), ("message", message.into())]),
+            ),
+        };
+        self.send(Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome]))
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]))
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Reply {
+        match method {
+            "initialize" => Ok(Json::object([
+                (
+                    "capabilities",
+                    Json::object([
+                        (
+                            "textDocumentSync",
+                            Json::object([
+                                ("openClose", true.into()),
+                                ("change", SYNC_FULL.into()),
+                                ("save", Json::object([("includeText", true.into())])),
+                            ]),
+                        ),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object([("name", "snd".into()), ("version", env!("CARGO_PKG_VERSION").into())]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                Ok(match document.hover(offset) {
+                    Some((context, text)) => Json::object([
+                        (
+                            "contents",
+                            Json::object([
+                                ("kind", "markdown".into()),
+                                ("value", format!("```snd\n{}\n```", text).into()),
+                            ]),
+                        ),
+                        ("range", document.range(context)),
+                    ]),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/definition" => {
+                let (document, offset) = self.position(params)?;
+                Ok(match document.definition(offset) {
+                    Some(context) => document.location(context),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/documentSymbol" => Ok(self.document(params)?.symbols()),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        }
+    }
+
+    /// The document a request is about
+    fn document(&self, params: &Json) -> Result<&Analysis, (i64, String)> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or_else(invalid_params)?;
+        self.documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("`{}` is not open", uri)))
+    }
+
+    /// The document and byte offset a request is about
+    fn position(&self, params: &Json) -> Result<(&Analysis, usize), (i64, String)> {
+        let document = self.document(params)?;
+        let offset = params
+            .get("position")
+            .and_then(|position| document.offset(position))
+            .ok_or_else(invalid_params)?;
+        Ok((document, offset))
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|d| d.get("uri")).and_then(Json::as_str) else {
+            return Ok(());
+        };
+
+        let text = match method {
+            "textDocument/didOpen" => document.and_then(|d| d.get("text")),
+            // with full sync, the last change holds the whole text
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(<[Json]>::last)
+                .and_then(|change| change.get("text")),
+            "textDocument/didSave" => params.get("text"),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                let params = Json::object([("uri", uri.into()), ("diagnostics", Json::Array(Vec::new()))]);
+                return self.notify("textDocument/publishDiagnostics", params);
+            }
+            _ => return Ok(()),
+        };
+
+        if let Some(text) = text.and_then(Json::as_str) {
+            self.documents.insert(uri.to_string(), Analysis::new(uri, text));
+        }
+        if let Some(document) = self.documents.get(uri) {
+            let params = Json::object([("uri", uri.into()), ("diagnostics", document.diagnostics())]);
+            self.notify("textDocument/publishDiagnostics", params)?;
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,314 @@
-mod util;
-mod ast;
-mod lexer;
-mod context;
+mod repl;
 
-use lexer::*;
+use std::io::{IsTerminal, Read};
+
+use snd_language::{ast::s0::Tree, cst, diagnostic::Diagnostic, exhaustive, fmt, lsp, types, vm, Engine, ErrorKind, Value};
+
+use snd_language::lexer::*;
+use snd_language::parser::*;
+
+const USAGE: &str = "\
+usage: snd [command] [options] [path | -]
+
+commands:
+  run      check and run a program (the default)
+  check    check a program without running it
+  lex      print a program's tokens
+  parse    print a program's syntax tree
+  fmt      print a program in the standard layout
+  repl     start an interactive session
+  lsp      serve the Language Server Protocol over stdin and stdout
+
+options:
+  --color=auto|always|never   color diagnostics (auto: when stderr is a terminal)
+  --error-format=human|json   how to print diagnostics
+  --emit=tokens|ast|cst|types print an intermediate result and stop
+  --check                     with fmt, fail if the program isn't formatted
+  --dump-bytecode             print the compiled program instead of running it
+  --gc-stats                  print garbage collector statistics after running
+  --gc-stress                 collect garbage before every allocation
+
+The program is read from stdin when the path is `-` or missing. Exits with 0
+on success, 1 for errors in the program or the command line, and 2 for errors
+while running it.";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Check,
+    Lex,
+    Parse,
+    Fmt,
+    Repl,
+    Lsp,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "run" => Some(Command::Run),
+            "check" => Some(Command::Check),
+            "lex" => Some(Command::Lex),
+            "parse" => Some(Command::Parse),
+            "fmt" => Some(Command::Fmt),
+            "repl" => Some(Command::Repl),
+            "lsp" => Some(Command::Lsp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    Tokens,
+    Ast,
+    Cst,
+    Types,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+/// How diagnostics are printed
+#[derive(Clone, Copy)]
+struct Reporter {
+    format: ErrorFormat,
+    color: bool,
+}
+
+impl Reporter {
+    fn report(&self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        for diag in diagnostics {
+            match self.format {
+                ErrorFormat::Human => eprintln!("{}", diag.render(self.color)),
+                ErrorFormat::Json => eprintln!("{}", diag.to_json()),
+            }
+        }
+    }
+
+    /// Reports errors in the program, and exits
+    fn fail(&self, diagnostics: impl IntoIterator<Item = Diagnostic>) -> ! {
+        self.report(diagnostics);
+        std::process::exit(1);
+    }
+}
+
+/// Whether `--color=auto` colors diagnostics
+fn auto_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(1);
+}
+
+struct Options {
+    command: Command,
+    path: Option<String>,
+    reporter: Reporter,
+    emit: Option<Emit>,
+    check: bool,
+    dump_bytecode: bool,
+    gc_stats: bool,
+    gc_stress: bool,
+}
+
+fn options() -> Options {
+    let mut args = std::env::args().skip(1).peekable();
+    let command = args.peek().and_then(|arg| Command::from_name(arg));
+    if command.is_some() {
+        args.next();
+    }
+
+    let mut options = Options {
+        command: command.unwrap_or(Command::Run),
+        path: None,
+        reporter: Reporter {
+            format: ErrorFormat::Human,
+            color: auto_color(),
+        },
+        emit: None,
+        check: false,
+        dump_bytecode: false,
+        gc_stats: false,
+        gc_stress: false,
+    };
+
+    for arg in args {
+        match arg.as_str() {
+            "--color=auto" => options.reporter.color = auto_color(),
+            "--color=always" => options.reporter.color = true,
+            "--color=never" => options.reporter.color = false,
+            "--error-format=human" => options.reporter.format = ErrorFormat::Human,
+            "--error-format=json" => options.reporter.format = ErrorFormat::Json,
+            "--emit=tokens" => options.emit = Some(Emit::Tokens),
+            "--emit=ast" => options.emit = Some(Emit::Ast),
+            "--emit=cst" => options.emit = Some(Emit::Cst),
+            "--emit=types" => options.emit = Some(Emit::Types),
+            "--check" => options.check = true,
+            "--dump-bytecode" => options.dump_bytecode = true,
+            "--gc-stats" => options.gc_stats = true,
+            "--gc-stress" => options.gc_stress = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "-" if options.path.is_none() => options.path = Some(arg),
+            _ if arg.starts_with('-') || options.path.is_some() => usage(),
+            _ => options.path = Some(arg),
+        }
+    }
+    options
+}
+
+/// Reads the program named by `path`, or stdin, with the name its
+/// diagnostics call it by
+fn read(path: Option<&str>, reporter: Reporter) -> (String, String) {
+    match path {
+        None | Some("-") => {
+            let mut src = String::new();
+            if let Err(err) = std::io::stdin().read_to_string(&mut src) {
+                eprintln!("could not read <stdin>: {}", err);
+                std::process::exit(1);
+            }
+            ("<stdin>".to_string(), src)
+        }
+        Some(path) => match snd_language::lexer::read(path) {
+            Ok(src) => (path.to_string(), src),
+            Err(err) => reporter.fail([err.diagnostic()]),
+        },
+    }
+}
+
+/// Lexes a program, keeping trivia
+fn lex(name: &str, src: &str, reporter: Reporter) -> Vec<Token> {
+    match Lexer::from_source(name, src).lex_with_trivia() {
+        Ok(tokens) => tokens,
+        Err(errors) => reporter.fail(errors.iter().map(LexError::diagnostic)),
+    }
+}
+
+fn parse(tokens: Vec<Token>, reporter: Reporter) -> Tree {
+    let mut parser = Parser::new(tokens);
+    let tree = parser.parse();
+    reporter.report(parser.warnings());
+
+    match tree {
+        Ok(tree) => tree,
+        Err(errors) => reporter.fail(errors.iter().map(ParseError::diagnostic)),
+    }
+}
 
 fn main() {
-    let path = std::env::args().nth(1).expect("no source file given");
+    let options = options();
+    let reporter = options.reporter;
 
-    let lexer = Lexer::new(&path);
-    let tokens = lexer.lex();
+    if options.command == Command::Repl {
+        return repl::run(reporter);
+    }
+    if options.command == Command::Lsp {
+        match lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("lsp: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (name, src) = read(options.path.as_deref(), reporter);
+    // what stops before running needs the stages the engine goes through
+    let stops_early = options.emit.is_some() || (options.dump_bytecode && options.command == Command::Run);
+    if matches!(options.command, Command::Run | Command::Check) && !stops_early {
+        return run(&options, &name, &src);
+    }
+
+    let all_tokens = lex(&name, &src, reporter);
+    let tokens = all_tokens.iter().filter(|t| !t.is_trivia()).cloned().collect();
+    let tokens = match layout(tokens) {
+        Ok(tokens) => tokens,
+        Err(errors) => reporter.fail(errors.iter().map(LexError::diagnostic)),
+    };
+
+    if options.command == Command::Lex || options.emit == Some(Emit::Tokens) {
+        for token in tokens.iter().filter(|t| t.token != TokenKind::Eof) {
+            println!("{} {}", token.context, token.token);
+        }
+        return;
+    }
+
+    let file = tokens[0].context.file;
+    let tree = parse(tokens, reporter);
+    if options.command == Command::Parse || options.emit == Some(Emit::Ast) {
+        println!("{}", tree);
+        return;
+    }
+    if options.emit == Some(Emit::Cst) {
+        print!("{}", cst::build(all_tokens, &tree).dump());
+        return;
+    }
+    if options.command == Command::Fmt {
+        let formatted = fmt::format(&tree, &fmt::comments(&all_tokens));
+        if !options.check {
+            print!("{}", formatted);
+        } else if let Some(diag) = fmt::unformatted(file, &formatted) {
+            reporter.fail([diag]);
+        }
+        return;
+    }
+
+    let typed = match types::check(&tree) {
+        Ok(typed) => typed,
+        Err(errors) => reporter.fail(errors.iter().map(types::TypeError::diagnostic)),
+    };
+    reporter.report(exhaustive::check(&tree));
+
+    if options.emit == Some(Emit::Types) {
+        for (name, scheme) in &typed.bindings {
+            println!("{} : {}", name, scheme);
+        }
+        return;
+    }
+
+    let mut vm = vm::Vm::new();
+    let program = vm.compile(&tree);
+    let disassembly = vm::bytecode::Disassembly {
+        proto: &program,
+        globals: &vm.globals,
+    };
+    print!("{}", disassembly);
+}
+
+/// Checks, and unless only checking, runs a program through the engine
+fn run(options: &Options, name: &str, src: &str) {
+    let reporter = options.reporter;
+    let mut engine = Engine::new();
+    engine.heap().stress = options.gc_stress;
+
+    let result = match options.command {
+        Command::Check => engine.check(name, src).map(|()| Value::Unit),
+        _ => engine.load(name, src),
+    };
+    reporter.report(engine.warnings());
+    if options.gc_stats && options.command == Command::Run {
+        eprintln!("{}", engine.heap().stats);
+    }
 
-    for token in tokens {
-        println!("{}", token.context.in_context());
+    match result {
+        Ok(Value::Unit) => {}
+        Ok(value) => println!("{}", value),
+        Err(err) => {
+            reporter.report(err.diagnostics().iter().cloned());
+            let code = match err.kind() {
+                ErrorKind::Compile => 1,
+                ErrorKind::Runtime => 2,
+            };
+            std::process::exit(code);
+        }
     }
 }
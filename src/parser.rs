@@ -0,0 +1,896 @@
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{
+    ast::s0::*,
+    context::Context,
+    diagnostic::Diagnostic,
+    lexer::{Token, TokenKind},
+    source,
+};
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Expected { expected: String, found: TokenKind },
+    ChainedOperator(BinOp),
+    Unclosed { open: Context, found: TokenKind },
+    /// What was found starts a line that ended `|` lines above it
+    Dedented { expected: String, found: TokenKind },
+    /// A `match` found the next arm of the `match` it is in, rather than its
+    /// own arms
+    OuterArm { inner: Context },
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub context: Context,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::Expected { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ParseErrorKind::ChainedOperator(op) => {
+                write!(f, "comparison operator `{}` cannot be chained", op)
+            }
+            ParseErrorKind::Unclosed { found, .. } => {
+                write!(f, "expected `)`, found {}", found)
+            }
+            ParseErrorKind::Dedented { expected, found } => {
+                write!(f, "expected {}, but the `|` lines above end before {}", expected, found)
+            }
+            ParseErrorKind::OuterArm { .. } => {
+                write!(f, "expected `|` for this `match`, found an arm of the `match` around it")
+            }
+        }
+    }
+}
+
+impl ParseError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.to_string());
+
+        match &self.kind {
+            ParseErrorKind::Expected { expected, .. } => diag
+                .with_code("E0100")
+                .with_label(self.context, format!("expected {}", expected)),
+            ParseErrorKind::ChainedOperator(_) => diag
+                .with_code("E0101")
+                .with_label(self.context, "")
+                .with_help("use parentheses, or `&&` to combine comparisons"),
+            ParseErrorKind::Unclosed { open, .. } => diag
+                .with_code("E0102")
+                .with_label(self.context, "expected `)`")
+                .with_secondary(*open, "unclosed delimiter"),
+            ParseErrorKind::Dedented { .. } => diag
+                .with_code("E0103")
+                .with_label(self.context, "this line ends them")
+                .with_note("a line indented no further than a block of `|` lines ends it")
+                .with_help("indent it further to continue the last arm"),
+            ParseErrorKind::OuterArm { inner } => diag
+                .with_code("E0104")
+                .with_label(self.context, "this lines up with the arms of the outer `match`")
+                .with_secondary(*inner, "this `match` has no arms")
+                .with_help("indent the inner `match`'s arms further than the `|` of the arm it is in"),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The last token taken, which may be further back than `pos - 1` if
+    /// dropped tokens were stepped over
+    last: Option<usize>,
+
+    /// Errors recovered from so far
+    errors: Vec<ParseError>,
+    /// Whether a `|` would start another arm of the innermost `match`
+    in_arms: bool,
+    /// The column of the item being parsed, or `None` inside parentheses
+    item_col: Option<usize>,
+    warnings: Vec<Diagnostic>,
+}
+
+fn is_cons_name(ident: &Ident) -> bool {
+    ident.name.name.starts_with(|c: char| c.is_uppercase())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+fn binop(token: &TokenKind) -> Option<BinOp> {
+    let op = match token {
+        TokenKind::Plus => BinOp::Add,
+        TokenKind::Minus => BinOp::Sub,
+        TokenKind::Star => BinOp::Mul,
+        TokenKind::Slash => BinOp::Div,
+        TokenKind::Percent => BinOp::Rem,
+        TokenKind::Lt => BinOp::Lt,
+        TokenKind::Le => BinOp::Le,
+        TokenKind::Gt => BinOp::Gt,
+        TokenKind::Ge => BinOp::Ge,
+        TokenKind::EqEq => BinOp::Eq,
+        TokenKind::NotEq => BinOp::NotEq,
+        TokenKind::AndAnd => BinOp::And,
+        TokenKind::OrOr => BinOp::Or,
+        _ => return None,
+    };
+    Some(op)
+}
+
+pub fn precedence(op: BinOp) -> (u8, Assoc) {
+    use BinOp::*;
+    match op {
+        Or => (1, Assoc::Right),
+        And => (2, Assoc::Right),
+        Eq | NotEq => (3, Assoc::None),
+        Lt | Le | Gt | Ge => (4, Assoc::None),
+        Add | Sub => (5, Assoc::Left),
+        Mul | Div | Rem => (6, Assoc::Left),
+    }
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            last: None,
+            errors: Vec::new(),
+            in_arms: false,
+            item_col: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        // the lexer always ends the stream with Eof
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> &Token {
+        let pos = self.pos.min(self.tokens.len() - 1);
+        self.last = Some(pos);
+        self.pos += 1;
+        // dropped tokens are never the one at `pos`
+        while self.tokens.get(self.pos).is_some_and(|token| token.token == TokenKind::None) {
+            self.pos += 1;
+        }
+        &self.tokens[pos]
+    }
+
+    fn at(&self, token: TokenKind) -> bool {
+        self.peek().token == token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek().token, TokenKind::Keyword(k) if k == keyword)
+    }
+
+    fn eat(&mut self, token: TokenKind) -> bool {
+        if self.at(token) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The next token that isn't layout, and whether a dedent comes first
+    fn found(&self) -> (&Token, bool) {
+        let mut pos = self.pos.min(self.tokens.len() - 1);
+        let mut dedented = false;
+        // the stream ends with Eof, which isn't layout
+        while self.tokens[pos].is_layout() || self.tokens[pos].token == TokenKind::None {
+            dedented |= self.tokens[pos].token == TokenKind::Dedent;
+            pos += 1;
+        }
+        (&self.tokens[pos], dedented)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T> {
+        let (found, dedented) = self.found();
+        let expected = expected.to_string();
+        let kind = match dedented {
+            true => ParseErrorKind::Dedented {
+                expected,
+                found: found.token.clone(),
+            },
+            false => ParseErrorKind::Expected {
+                expected,
+                found: found.token.clone(),
+            },
+        };
+        Err(ParseError {
+            kind,
+            context: found.context,
+        })
+    }
+
+    fn expected_at(&self, expected: &str, context: Context) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Expected {
+                expected: expected.to_string(),
+                found: self.tokens[self.last.unwrap()].token.clone(),
+            },
+            context,
+        }
+    }
+
+    fn expect(&mut self, token: TokenKind, expected: &str) -> Result<Context> {
+        if self.at(token) {
+            Ok(self.next().context)
+        } else {
+            self.error(expected)
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<Context> {
+        if self.at_keyword(keyword) {
+            Ok(self.next().context)
+        } else {
+            self.error(&format!("`{}`", keyword))
+        }
+    }
+
+    fn ident(&mut self) -> Result<Ident> {
+        match self.peek().token {
+            TokenKind::Ident(name) => {
+                let context = self.next().context;
+                Ok(Ident { name, context })
+            }
+            _ => self.error("identifier"),
+        }
+    }
+
+    /// Expects the `)` matching the `(` at `open`
+    fn close(&mut self, open: Context) -> Result<Context> {
+        if self.at(TokenKind::RParen) {
+            return Ok(self.next().context);
+        }
+
+        let (found, _) = self.found();
+        Err(ParseError {
+            kind: ParseErrorKind::Unclosed {
+                open,
+                found: found.token.clone(),
+            },
+            context: found.context,
+        })
+    }
+
+    /// Parses parenthesized, comma-separated items
+    fn comma_list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<(Vec<T>, Context)> {
+        let open = self.expect(TokenKind::LParen, "`(`")?;
+        // the lines in parentheses can't start items
+        let item_col = self.item_col.take();
+        let list = self.comma_items(open, &mut item);
+        self.item_col = item_col;
+        list
+    }
+
+    fn comma_items<T>(
+        &mut self,
+        open: Context,
+        item: &mut impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<(Vec<T>, Context)> {
+        let mut items = Vec::new();
+
+        while !self.at(TokenKind::RParen) {
+            match item(self) {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    // the list goes on if its end is found, and otherwise
+                    // the error ends it
+                    self.synchronize(|token| *token == TokenKind::Comma);
+                    if !self.at(TokenKind::Comma) && !self.at(TokenKind::RParen) {
+                        return Err(err);
+                    }
+                    self.report(err);
+                }
+            }
+            if !self.eat(TokenKind::Comma) {
+                break;
+            }
+        }
+
+        let end = self.close(open)?;
+        Ok((items, end))
+    }
+
+    /// Takes the warnings reported so far
+    pub fn warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Parses a program, failing with every syntax error in it
+    pub fn parse(&mut self) -> std::result::Result<Tree, Vec<ParseError>> {
+        let (tree, errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(tree)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses a program as far as possible, with `Tree::Error` in place of
+    /// the parts that have syntax errors
+    pub fn parse_partial(&mut self) -> (Tree, Vec<ParseError>) {
+        let start = self.peek().context;
+        let mut items = Vec::new();
+
+        while !self.at(TokenKind::Eof) {
+            items.push(self.recovering_item());
+        }
+
+        let context = start.to(&self.peek().context);
+        (Tree::Block { items, context }, std::mem::take(&mut self.errors))
+    }
+
+    /// Records an error to carry on from, unless one was already found at
+    /// the same token, as when two constructs stop at one stray `)`
+    fn report(&mut self, err: ParseError) {
+        if self.errors.last().is_none_or(|last| last.context != err.context) {
+            self.errors.push(err);
+        }
+    }
+
+    /// The context from `start` to the last token taken
+    fn since(&self, start: Context) -> Context {
+        match self.last {
+            Some(last) if self.tokens[last].context.start >= start.start => start.to(&self.tokens[last].context),
+            _ => start,
+        }
+    }
+
+    /// Skips to the next token at the same nesting that `stop` accepts, or
+    /// that can only start an item or arm, or close an enclosing delimiter or
+    /// block of `|` lines
+    fn synchronize(&mut self, stop: impl Fn(&TokenKind) -> bool) {
+        let mut depth = 0usize;
+        loop {
+            let token = &self.peek().token;
+            let next_arm = self.in_arms && matches!(token, TokenKind::Pipe | TokenKind::Newline);
+            if depth == 0 && (stop(token) || next_arm || matches!(token, TokenKind::Keyword("let" | "data"))) {
+                return;
+            }
+            match token {
+                TokenKind::Eof => return,
+                TokenKind::LParen | TokenKind::LBrace | TokenKind::Indent => depth += 1,
+                TokenKind::RParen | TokenKind::RBrace | TokenKind::Dedent if depth == 0 => return,
+                TokenKind::RParen | TokenKind::RBrace | TokenKind::Dedent => depth -= 1,
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Whether another `|` alternative follows, taking the layout before it;
+    /// `layout` is whether the alternatives are in a block of `|` lines,
+    /// which has to be closed where they end
+    fn alternative(&mut self, layout: &mut bool) -> bool {
+        if *layout {
+            self.eat(TokenKind::Newline);
+        } else if self.eat(TokenKind::Indent) {
+            *layout = true;
+        }
+
+        if self.at(TokenKind::Pipe) {
+            return true;
+        }
+        if *layout {
+            // what follows the last arm on its line ends the block early,
+            // so the dedent at the block's end has nothing left to close
+            if !self.eat(TokenKind::Dedent) {
+                self.drop_dedent();
+            }
+            *layout = false;
+        }
+        false
+    }
+
+    /// Drops the dedent that closes the block of `|` lines the parser is in,
+    /// turning it into a `None` token for [`Parser::next`] to step over, as
+    /// removing it would move every token after it
+    fn drop_dedent(&mut self) {
+        let mut depth = 0usize;
+        for pos in self.pos..self.tokens.len() {
+            match self.tokens[pos].token {
+                TokenKind::Indent => depth += 1,
+                TokenKind::Dedent if depth == 0 => {
+                    self.tokens[pos].token = TokenKind::None;
+                    return;
+                }
+                TokenKind::Dedent => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// An item, or after a syntax error in it, an error node covering what
+    /// was skipped
+    fn recovering_item(&mut self) -> Tree {
+        let start = self.peek().context;
+        let pos = self.pos;
+        match self.item() {
+            Ok(item) => item,
+            Err(err) => {
+                self.report(err);
+                self.synchronize(|_| false);
+                // items are never in parentheses, so a `)` here is stray,
+                // and so is any token that can't start anything
+                if self.pos == pos || self.at(TokenKind::RParen) {
+                    self.next();
+                }
+                // so are the ends of blocks of `|` lines the error was in
+                while self.eat(TokenKind::Dedent) {}
+                Tree::Error(self.since(start))
+            }
+        }
+    }
+
+    fn item(&mut self) -> Result<Tree> {
+        let col = self.column(self.peek().context);
+        let item_col = self.item_col.replace(col);
+        let item = if self.at_keyword("data") {
+            self.data().map(Tree::Data)
+        } else if self.at_keyword("let") {
+            self.binding()
+        } else {
+            self.expr()
+        };
+        self.item_col = item_col;
+        item
+    }
+
+    fn column(&self, context: Context) -> usize {
+        source::get(context.file).line_col(context.start).1
+    }
+
+    /// Whether the next token starts a line no further right than the item
+    /// being parsed, and so starts the next item. Only `(` and `-` need it:
+    /// the other tokens that can start an item can't continue one.
+    fn at_next_item(&self) -> bool {
+        let (Some(col), Some(last)) = (self.item_col, self.last) else {
+            return false;
+        };
+        let file = source::get(self.peek().context.file);
+        let (line, next_col) = file.line_col(self.peek().context.start);
+        line != file.line_col(self.tokens[last].context.start).0 && next_col <= col
+    }
+
+    fn data(&mut self) -> Result<DataDef> {
+        let start = self.expect_keyword("data")?;
+        let name = self.ident()?;
+
+        let mut params = Vec::new();
+        while !self.at(TokenKind::Equals) {
+            let param = self.ident()?;
+            if is_cons_name(&param) {
+                return Err(self.expected_at("a lowercase type parameter", param.context));
+            }
+            params.push(param);
+        }
+        self.expect(TokenKind::Equals, "`=`")?;
+
+        let mut cons = vec![self.data_cons()?];
+        let mut layout = false;
+        while self.alternative(&mut layout) {
+            self.next();
+            cons.push(self.data_cons()?);
+        }
+
+        let context = start.to(&cons.last().unwrap().context);
+        Ok(DataDef {
+            name,
+            params,
+            cons,
+            context,
+        })
+    }
+
+    fn data_cons(&mut self) -> Result<DataCons> {
+        let name = self.ident()?;
+        let mut context = name.context;
+        let mut fields = Vec::new();
+
+        if self.at(TokenKind::LParen) {
+            let (types, end) = self.comma_list(Self::ty)?;
+            fields = types;
+            context = context.to(&end);
+        }
+
+        Ok(DataCons { name, fields, context })
+    }
+
+    /// A type, possibly applied to arguments as in `List (Pair a b)`
+    fn ty(&mut self) -> Result<Type> {
+        if self.at(TokenKind::LParen) {
+            return self.type_atom();
+        }
+
+        let name = self.ident()?;
+        if !is_cons_name(&name) {
+            return Ok(Type::Var(name));
+        }
+
+        let mut args = Vec::new();
+        while matches!(self.peek().token, TokenKind::Ident(_) | TokenKind::LParen) {
+            args.push(self.type_atom()?);
+        }
+
+        let context = match args.last() {
+            Some(last) => name.context.to(&last.context()),
+            None => name.context,
+        };
+        Ok(Type::Named {
+            name,
+            args,
+            context,
+        })
+    }
+
+    /// A type variable, a type name without arguments, or a parenthesized type
+    fn type_atom(&mut self) -> Result<Type> {
+        if self.at(TokenKind::LParen) {
+            let open = self.next().context;
+            let ty = self.ty()?;
+            self.close(open)?;
+            return Ok(ty);
+        }
+
+        let name = self.ident()?;
+        if is_cons_name(&name) {
+            Ok(Type::Named {
+                name,
+                args: Vec::new(),
+                context: name.context,
+            })
+        } else {
+            Ok(Type::Var(name))
+        }
+    }
+
+    fn binding(&mut self) -> Result<Tree> {
+        let start = self.expect_keyword("let")?;
+        let name = self.ident()?;
+        self.expect(TokenKind::Equals, "`=`")?;
+        let value = self.expr()?;
+
+        let context = start.to(&value.context());
+        Ok(Tree::Let {
+            name,
+            value: Box::new(value),
+            context,
+        })
+    }
+
+    pub fn expr(&mut self) -> Result<Tree> {
+        self.binary(0)
+    }
+
+    /// Precedence climbing over binary operators, loosest first:
+    ///
+    /// | prec | operators            | associativity |
+    /// |------|----------------------|---------------|
+    /// | 1    | `||`                 | right         |
+    /// | 2    | `&&`                 | right         |
+    /// | 3    | `==` `!=`            | none          |
+    /// | 4    | `<` `<=` `>` `>=`    | none          |
+    /// | 5    | `+` `-`              | left          |
+    /// | 6    | `*` `/` `%`          | left          |
+    ///
+    /// Prefix `-` and `!` bind tighter than any binary operator but looser
+    /// than calls, so `-f(x)` is `-(f(x))`. Non-associative operators can't
+    /// be chained: `a < b < c` is an error rather than `(a < b) < c`.
+    ///
+    /// Expressions go on across lines, except that a `-` or `(` starting a
+    /// line no further right than the item it would continue starts the next
+    /// item instead, so `let a = 1` and then `(a)` on the next line are two
+    /// items rather than the call `1(a)`.
+    fn binary(&mut self, min_prec: u8) -> Result<Tree> {
+        let mut lhs = self.unary()?;
+        let mut last_prec = None;
+
+        while let Some(op) = binop(&self.peek().token) {
+            let (prec, assoc) = precedence(op);
+            if prec < min_prec || (op == BinOp::Sub && self.at_next_item()) {
+                break;
+            }
+
+            // read on as if it were left associative
+            if assoc == Assoc::None && last_prec == Some(prec) {
+                self.report(ParseError {
+                    kind: ParseErrorKind::ChainedOperator(op),
+                    context: self.peek().context,
+                });
+            }
+
+            self.next();
+            let rhs = match assoc {
+                Assoc::Left | Assoc::None => self.binary(prec + 1)?,
+                Assoc::Right => self.binary(prec)?,
+            };
+
+            let context = lhs.context().to(&rhs.context());
+            lhs = Tree::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                context,
+            };
+            last_prec = Some(prec);
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Tree> {
+        let op = match self.peek().token {
+            TokenKind::Minus => UnOp::Neg,
+            TokenKind::Bang => UnOp::Not,
+            _ => return self.postfix(),
+        };
+
+        let start = self.next().context;
+        let operand = self.unary()?;
+
+        let context = start.to(&operand.context());
+        Ok(Tree::Unary {
+            op,
+            operand: Box::new(operand),
+            context,
+        })
+    }
+
+    fn postfix(&mut self) -> Result<Tree> {
+        let mut func = self.primary()?;
+
+        while self.at(TokenKind::LParen) && !self.at_next_item() {
+            let (args, end) = self.comma_list(Self::expr)?;
+            let context = func.context().to(&end);
+            func = Tree::Call {
+                func: Box::new(func),
+                args,
+                context,
+            };
+        }
+
+        Ok(func)
+    }
+
+    fn primary(&mut self) -> Result<Tree> {
+        let token = self.peek();
+        let context = token.context;
+
+        match token.token {
+            TokenKind::Keyword("fn") => self.func(),
+            TokenKind::Keyword("match") => self.matches(),
+            TokenKind::Keyword("if") => self.cond(),
+            TokenKind::Ident(name) => {
+                self.next();
+                Ok(Tree::Var(Ident { name, context }))
+            }
+            TokenKind::IntLit(value) => {
+                self.next();
+                Ok(Tree::IntLit { value, context })
+            }
+            TokenKind::BoolLit(value) => {
+                self.next();
+                Ok(Tree::BoolLit { value, context })
+            }
+            TokenKind::StrLit(ref value) => {
+                let value = value.clone();
+                self.next();
+                Ok(Tree::StrLit { value, context })
+            }
+            TokenKind::CharLit(value) => {
+                self.next();
+                Ok(Tree::CharLit { value, context })
+            }
+            TokenKind::LParen => {
+                self.next();
+                let item_col = self.item_col.take();
+                let inner = self.expr();
+                self.item_col = item_col;
+                let inner = match inner {
+                    Ok(inner) => inner,
+                    Err(err) => {
+                        self.synchronize(|_| false);
+                        if !self.at(TokenKind::RParen) {
+                            return Err(err);
+                        }
+                        self.report(err);
+                        self.next();
+                        return Ok(Tree::Error(self.since(context)));
+                    }
+                };
+                self.close(context)?;
+                Ok(inner)
+            }
+            TokenKind::LBrace => {
+                self.next();
+                let mut items = Vec::new();
+                let in_arms = std::mem::replace(&mut self.in_arms, false);
+                while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
+                    items.push(self.recovering_item());
+                }
+                self.in_arms = in_arms;
+                let end = self.expect(TokenKind::RBrace, "`}`")?;
+                Ok(Tree::Block {
+                    items,
+                    context: context.to(&end),
+                })
+            }
+            _ => self.error("expression"),
+        }
+    }
+
+    fn func(&mut self) -> Result<Tree> {
+        let start = self.expect_keyword("fn")?;
+        let (params, _) = self.comma_list(Self::ident)?;
+        self.expect(TokenKind::Equals, "`=`")?;
+        let body = self.expr()?;
+
+        let context = start.to(&body.context());
+        Ok(Tree::Fn {
+            params,
+            body: Rc::new(body),
+            context,
+        })
+    }
+
+    fn matches(&mut self) -> Result<Tree> {
+        let start = self.expect_keyword("match")?;
+        let scrutinee = self.expr()?;
+
+        let mut arms = Vec::new();
+        let mut layout = false;
+        let mut recovered = false;
+        let in_arms = std::mem::replace(&mut self.in_arms, true);
+        loop {
+            let pos = self.pos;
+            if !self.alternative(&mut layout) {
+                break;
+            }
+            match self.arm() {
+                Ok(arm) => arms.push(arm),
+                Err(err) => {
+                    self.report(err);
+                    self.synchronize(|_| false);
+                    // brackets around a block of `|` lines close after it,
+                    // so any closing in it is stray
+                    while layout && (self.at(TokenKind::RParen) || self.at(TokenKind::RBrace)) {
+                        self.next();
+                        self.synchronize(|_| false);
+                    }
+                    recovered = true;
+                    // nothing here can be an arm
+                    if self.pos == pos {
+                        break;
+                    }
+                }
+            }
+        }
+        self.in_arms = in_arms;
+
+        if arms.is_empty() {
+            return match recovered {
+                true => Ok(Tree::Error(self.since(start))),
+                false if self.at(TokenKind::Newline) => Err(ParseError {
+                    kind: ParseErrorKind::OuterArm { inner: start },
+                    context: self.found().0.context,
+                }),
+                false => self.error("`|`"),
+            };
+        }
+
+        let context = start.to(&arms.last().unwrap().context);
+        Ok(Tree::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+            context,
+        })
+    }
+
+    fn arm(&mut self) -> Result<MatchArm> {
+        let start = self.expect(TokenKind::Pipe, "`|`")?;
+        let pattern = self.pattern()?;
+
+        // `=` is accepted as a lenient spelling of `=>`
+        if self.at(TokenKind::Equals) {
+            let context = self.next().context;
+            self.warnings.push(
+                Diagnostic::warning("match arm uses `=` instead of `=>`")
+                    .with_code("W0100")
+                    .with_label(context, "")
+                    .with_help("write `=>`"),
+            );
+        } else if !self.eat(TokenKind::FatArrow) {
+            return self.error("`=>`");
+        }
+
+        let body = self.expr()?;
+        let context = start.to(&body.context());
+        Ok(MatchArm {
+            pattern,
+            body,
+            context,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern> {
+        let token = self.peek();
+        let context = token.context;
+
+        match token.token {
+            TokenKind::IntLit(value) => {
+                self.next();
+                Ok(Pattern::IntLit { value, context })
+            }
+            TokenKind::BoolLit(value) => {
+                self.next();
+                Ok(Pattern::BoolLit { value, context })
+            }
+            TokenKind::StrLit(ref value) => {
+                let value = value.clone();
+                self.next();
+                Ok(Pattern::StrLit { value, context })
+            }
+            TokenKind::CharLit(value) => {
+                self.next();
+                Ok(Pattern::CharLit { value, context })
+            }
+            _ => {
+                let name = self.ident()?;
+
+                if name.name.name == "_" {
+                    Ok(Pattern::Wildcard(context))
+                } else if is_cons_name(&name) {
+                    let mut args = Vec::new();
+                    let mut context = context;
+
+                    if self.at(TokenKind::LParen) {
+                        let (pats, end) = self.comma_list(Self::pattern)?;
+                        args = pats;
+                        context = context.to(&end);
+                    }
+
+                    Ok(Pattern::Cons {
+                        name,
+                        args,
+                        context,
+                    })
+                } else {
+                    Ok(Pattern::Bind(name))
+                }
+            }
+        }
+    }
+
+    fn cond(&mut self) -> Result<Tree> {
+        let start = self.expect_keyword("if")?;
+        let cond = self.expr()?;
+        let then = self.expr()?;
+        self.expect_keyword("else")?;
+        let els = self.expr()?;
+
+        let context = start.to(&els.context());
+        Ok(Tree::If {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+            context,
+        })
+    }
+}
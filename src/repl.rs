@@ -0,0 +1,215 @@
+//! `snd repl`: reads entries a line at a time, keeping their definitions
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use snd_language::{
+    ast::s0::Tree,
+    diagnostic::Diagnostic,
+    lexer::*,
+    parser::*,
+    source::{self, FileId},
+    Engine, Value,
+};
+
+use crate::Reporter;
+
+const HELP: &str = "\
+Enter definitions and expressions to run them. A line that leaves a `match`,
+`fn(...) =` or bracket unfinished continues on the next; multi-line entries
+end with a blank line.
+
+:type <expr>     show the type of an expression
+:tokens <expr>   show the tokens of an expression
+:ast <expr>      show the syntax tree of an expression
+:load <path>     run a file, keeping its definitions
+:help            show this message
+:quit            leave (as does Ctrl-D)";
+
+struct Repl {
+    reporter: Reporter,
+    engine: Engine,
+}
+
+pub fn run(reporter: Reporter) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("could not start the repl: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut repl = Repl {
+        reporter,
+        engine: Engine::new(),
+    };
+    let mut entry = String::new();
+
+    loop {
+        let prompt = if entry.is_empty() { "snd> " } else { "...> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C abandons the current entry
+            Err(ReadlineError::Interrupted) => {
+                entry.clear();
+                continue;
+            }
+            // the end of input finishes an entry as a blank line would
+            Err(ReadlineError::Eof) => {
+                if !entry.is_empty() {
+                    repl.entry("<repl>", &entry);
+                }
+                break;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                break;
+            }
+        };
+
+        if entry.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(line.as_str());
+
+            if let Some(command) = line.trim().strip_prefix(':') {
+                if !repl.command(command) {
+                    break;
+                }
+                continue;
+            }
+        } else if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        let multi_line = !entry.is_empty();
+        entry.push_str(&line);
+        entry.push('\n');
+
+        if incomplete(&entry) || (multi_line && !line.trim().is_empty()) {
+            continue;
+        }
+        repl.entry("<repl>", &std::mem::take(&mut entry));
+    }
+}
+
+/// Whether `src` stops in the middle of something, and more lines may finish it
+fn incomplete(src: &str) -> bool {
+    let lexer = Lexer::from_source("<repl>", src);
+    let file = lexer.file();
+    let result = match lexer.lex() {
+        // later errors may only follow from the first
+        Ok(tokens) => match Parser::new(tokens).parse().map_err(|errors| errors.into_iter().next()) {
+            Err(Some(ParseError {
+                kind:
+                    ParseErrorKind::Expected { found, .. }
+                    | ParseErrorKind::Unclosed { found, .. }
+                    | ParseErrorKind::Dedented { found, .. },
+                ..
+            })) => found == TokenKind::Eof,
+            _ => false,
+        },
+        Err(errors) => errors
+            .iter()
+            .any(|err| matches!(err.kind, LexErrorKind::UnterminatedComment)),
+    };
+    source::remove(file);
+    result
+}
+
+impl Repl {
+    fn report(&self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        self.reporter.report(diagnostics);
+    }
+
+    /// Lexes and parses `src` as its own source file, which is removed
+    /// again if that fails
+    fn parse(&self, name: &str, src: &str) -> Option<(Tree, FileId)> {
+        let lexer = Lexer::from_source(name, src);
+        let file = lexer.file();
+
+        let tokens = match lexer.lex() {
+            Ok(tokens) => tokens,
+            Err(errors) => {
+                self.report(errors.iter().map(LexError::diagnostic));
+                source::remove(file);
+                return None;
+            }
+        };
+
+        let mut parser = Parser::new(tokens);
+        let tree = parser.parse();
+        self.report(parser.warnings());
+
+        match tree {
+            Ok(tree) => Some((tree, file)),
+            Err(errors) => {
+                self.report(errors.iter().map(ParseError::diagnostic));
+                source::remove(file);
+                None
+            }
+        }
+    }
+
+    /// Runs a command, returning false to quit
+    fn command(&mut self, command: &str) -> bool {
+        let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let arg = arg.trim();
+
+        match name {
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{}", HELP),
+            "t" | "type" => {
+                let result = self.engine.type_of("<repl>", arg);
+                self.reporter.report(self.engine.warnings());
+                match result {
+                    Ok(ty) => println!("{}", ty),
+                    Err(err) => self.report(err.diagnostics().iter().cloned()),
+                }
+            }
+            "tokens" => {
+                let lexer = Lexer::from_source("<repl>", arg);
+                let file = lexer.file();
+                match lexer.lex() {
+                    Ok(tokens) => {
+                        for token in tokens.iter().filter(|t| t.token != TokenKind::Eof) {
+                            println!("{} {}", token.context, token.token);
+                        }
+                    }
+                    Err(errors) => self.report(errors.iter().map(LexError::diagnostic)),
+                }
+                source::remove(file);
+            }
+            "ast" => {
+                if let Some((tree, file)) = self.parse("<repl>", arg) {
+                    println!("{}", tree);
+                    source::remove(file);
+                }
+            }
+            "l" | "load" => match std::fs::read_to_string(arg) {
+                Ok(src) => self.entry(arg, &src),
+                Err(err) => eprintln!("could not read {}: {}", arg, err),
+            },
+            _ => eprintln!("unknown command `:{}`; try :help", name),
+        }
+        true
+    }
+
+    /// Checks and runs an entry, keeping its definitions
+    fn entry(&mut self, name: &str, src: &str) {
+        let result = self.engine.load_typed(name, src);
+        self.reporter.report(self.engine.warnings());
+        match result {
+            Ok(loaded) => {
+                for (name, ty) in &loaded.definitions {
+                    println!("{} : {}", name, ty);
+                }
+                if loaded.value != Value::Unit {
+                    println!("{}", loaded.value);
+                }
+            }
+            Err(err) => self.report(err.diagnostics().iter().cloned()),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+use lazy_static::lazy_static;
+
+/// A file's slot in the map, and which of the files loaded into that slot
+/// it is, since removed files' slots are reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub src: String,
+
+    /// Byte offset of the start of every line
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(name: &str, src: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            src: src.to_string(),
+            line_starts,
+        }
+    }
+
+    /// 1-based line and column of a byte offset, counting columns in chars
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.src.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let col = self.src[self.line_starts[line - 1]..offset].chars().count() + 1;
+        (line, col)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Byte offset where the 1-based `line` starts
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line - 1]
+    }
+
+    /// Text of the 1-based `line`, without its line ending
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_start(line);
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.src.len(), |&next| next - 1);
+        self.src[start..end].trim_end_matches('\r')
+    }
+}
+
+/// Owns every loaded source file, handing out ids that contexts refer to
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<Slot>,
+    /// Slots of removed files, free to reuse once nothing holds them
+    removed: Vec<u32>,
+}
+
+struct Slot {
+    generation: u32,
+    entry: Entry,
+}
+
+enum Entry {
+    Loaded(Arc<SourceFile>),
+    /// Removed, but still readable while something [`Hold`]s it
+    Removed(Weak<SourceFile>),
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: &str, src: &str) -> FileId {
+        let entry = Entry::Loaded(Arc::new(SourceFile::new(name, src)));
+
+        let free = self.removed.iter().position(|&index| match &self.files[index as usize].entry {
+            Entry::Removed(source) => source.strong_count() == 0,
+            Entry::Loaded(_) => unreachable!("removed files are never loaded again"),
+        });
+        let Some(free) = free else {
+            self.files.push(Slot { generation: 0, entry });
+            return FileId {
+                index: self.files.len() as u32 - 1,
+                generation: 0,
+            };
+        };
+
+        let index = self.removed.swap_remove(free);
+        let slot = &mut self.files[index as usize];
+        slot.generation += 1;
+        slot.entry = entry;
+        FileId {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    pub fn get(&self, file: FileId) -> Arc<SourceFile> {
+        self.try_get(file).expect("source file used after removal")
+    }
+
+    fn try_get(&self, file: FileId) -> Option<Arc<SourceFile>> {
+        let slot = &self.files[file.index as usize];
+        if slot.generation != file.generation {
+            return None;
+        }
+        match &slot.entry {
+            Entry::Loaded(source) => Some(source.clone()),
+            Entry::Removed(source) => source.upgrade(),
+        }
+    }
+
+    /// Frees a file's text, once nothing will resolve contexts into it
+    /// except what holds it
+    pub fn remove(&mut self, file: FileId) {
+        let slot = &mut self.files[file.index as usize];
+        if slot.generation != file.generation {
+            return;
+        }
+        if let Entry::Loaded(source) = &slot.entry {
+            slot.entry = Entry::Removed(Arc::downgrade(source));
+            self.removed.push(file.index);
+        }
+    }
+}
+
+/// Keeps a file readable after it's removed, for as long as this lives
+#[derive(Clone)]
+pub struct Hold(Arc<SourceFile>);
+
+impl fmt::Debug for Hold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hold({})", self.0.name)
+    }
+}
+
+lazy_static! {
+    static ref SOURCES: Mutex<SourceMap> = Mutex::new(SourceMap::default());
+}
+
+pub fn add(name: &str, src: &str) -> FileId {
+    SOURCES.lock().unwrap().add(name, src)
+}
+
+pub fn get(file: FileId) -> Arc<SourceFile> {
+    SOURCES.lock().unwrap().get(file)
+}
+
+pub fn remove(file: FileId) {
+    SOURCES.lock().unwrap().remove(file)
+}
+
+/// Holds `file` if it can still be read
+pub fn hold(file: FileId) -> Option<Hold> {
+    SOURCES.lock().unwrap().try_get(file).map(Hold)
+}
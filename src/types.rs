@@ -0,0 +1,867 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    ast::s0::{self, BinOp, DataDef, Ident, Pattern, Tree, UnOp},
+    context::Context,
+    diagnostic::Diagnostic,
+    util::Symbol,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Var(usize),
+    /// A named type such as `Int` or a user `data` type, with its arguments
+    Con(&'static Symbol, Vec<Type>),
+    Fn(Vec<Type>, Box<Type>),
+}
+
+/// A type with its quantified variables, as given to `let`-bound names
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Scheme {
+        Scheme { vars: Vec::new(), ty }
+    }
+}
+
+/// Names type variables `'a`, `'b`, ... in order of appearance, so types
+/// printed with the same namer agree on their variables
+#[derive(Default)]
+struct Namer {
+    names: HashMap<usize, usize>,
+}
+
+impl Namer {
+    fn name(&mut self, var: usize) -> String {
+        let next = self.names.len();
+        let n = *self.names.entry(var).or_insert(next);
+
+        let letter = (b'a' + (n % 26) as u8) as char;
+        match n / 26 {
+            0 => format!("'{}", letter),
+            k => format!("'{}{}", letter, k),
+        }
+    }
+
+    fn show(&mut self, ty: &Type) -> String {
+        match ty {
+            Type::Var(v) => self.name(*v),
+            Type::Con(name, args) => {
+                let mut s = name.name.to_string();
+                for arg in args {
+                    match arg {
+                        Type::Con(_, args) if !args.is_empty() => s += &format!(" ({})", self.show(arg)),
+                        Type::Fn(..) => s += &format!(" ({})", self.show(arg)),
+                        _ => s += &format!(" {}", self.show(arg)),
+                    }
+                }
+                s
+            }
+            Type::Fn(params, ret) => {
+                let params = params.iter().map(|p| self.show(p)).collect::<Vec<_>>();
+                format!("fn({}) -> {}", params.join(", "), self.show(ret))
+            }
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Namer::default().show(self))
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.ty)
+    }
+}
+
+#[derive(Debug)]
+pub enum TypeErrorKind {
+    Mismatch { expected: String, found: String },
+    Undefined(&'static Symbol),
+    UnknownType(&'static Symbol),
+    DuplicateType(&'static Symbol),
+    DuplicateConstructor(&'static Symbol),
+    UnboundTypeVar(&'static Symbol),
+    TypeArity { name: &'static Symbol, expected: usize, found: usize },
+    UnknownConstructor(&'static Symbol),
+    ConsArity { name: &'static Symbol, expected: usize, found: usize },
+    ArgCount { expected: usize, found: usize },
+    InfiniteType { var: String, ty: String },
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub context: Context,
+    /// Where the expected type came from, if that's somewhere else
+    pub origin: Option<(Context, String)>,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use TypeErrorKind::*;
+        match &self.kind {
+            Mismatch { .. } => write!(f, "mismatched types"),
+            Undefined(name) => write!(f, "`{}` is not defined", name.name),
+            UnknownType(name) => write!(f, "unknown type `{}`", name.name),
+            DuplicateType(name) => write!(f, "type `{}` is already defined", name.name),
+            DuplicateConstructor(name) => write!(f, "constructor `{}` is already defined", name.name),
+            UnboundTypeVar(name) => write!(f, "type variable `{}` is not a parameter of this type", name.name),
+            TypeArity { name, expected, found } => write!(
+                f,
+                "type `{}` takes {} arguments but {} were given",
+                name.name, expected, found
+            ),
+            UnknownConstructor(name) => write!(f, "unknown constructor `{}`", name.name),
+            ConsArity { name, expected, found } => write!(
+                f,
+                "constructor `{}` has {} fields but the pattern has {}",
+                name.name, expected, found
+            ),
+            ArgCount { expected, found } => write!(
+                f,
+                "function takes {} arguments but {} were given",
+                expected, found
+            ),
+            InfiniteType { .. } => write!(f, "infinite type"),
+        }
+    }
+}
+
+impl TypeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        use TypeErrorKind::*;
+        let diag = Diagnostic::error(self.to_string());
+
+        let diag = match &self.kind {
+            Mismatch { expected, found } if expected == found => diag
+                .with_code("E0200")
+                .with_label(self.context, format!("expected {}, found {}", expected, found))
+                .with_note("these are different types of the same name; a type declared again shadows the earlier one"),
+            Mismatch { expected, found } => diag
+                .with_code("E0200")
+                .with_label(self.context, format!("expected {}, found {}", expected, found)),
+            Undefined(_) => diag.with_code("E0201").with_label(self.context, "not found in scope"),
+            UnknownType(_) => diag.with_code("E0202").with_label(self.context, "not a known type"),
+            DuplicateType(_) => diag
+                .with_code("E0209")
+                .with_label(self.context, "redefined here")
+                .with_help("give the new type another name"),
+            DuplicateConstructor(_) => diag
+                .with_code("E0210")
+                .with_label(self.context, "redefined here")
+                .with_help("give the new constructor another name"),
+            UnboundTypeVar(name) => diag
+                .with_code("E0207")
+                .with_label(self.context, "")
+                .with_help(format!("add `{}` to the parameters after the type's name", name.name)),
+            TypeArity { .. } => diag.with_code("E0208").with_label(self.context, ""),
+            UnknownConstructor(_) => diag
+                .with_code("E0203")
+                .with_label(self.context, "not a constructor of any `data` type"),
+            ConsArity { .. } => diag.with_code("E0204").with_label(self.context, ""),
+            ArgCount { expected, .. } => diag
+                .with_code("E0205")
+                .with_label(self.context, format!("expected {} arguments", expected)),
+            InfiniteType { var, ty } => diag
+                .with_code("E0206")
+                .with_label(self.context, format!("{} would have to contain itself, as {}", var, ty)),
+        };
+
+        match &self.origin {
+            Some((context, message)) => diag.with_secondary(*context, message.clone()),
+            None => diag,
+        }
+    }
+}
+
+/// Inference results for a program
+#[derive(Debug, Default)]
+pub struct Typed {
+    /// Every `let`, in source order, with its generalized type
+    pub bindings: Vec<(Ident, Scheme)>,
+    /// The type of every other name where it's bound or used, such as
+    /// parameters and variables
+    pub names: Vec<(Context, Type)>,
+}
+
+pub fn check(tree: &Tree) -> Result<Typed, Vec<TypeError>> {
+    Session::new().check(tree)
+}
+
+/// Definitions kept from one check to the next, so a program can use what
+/// earlier ones defined, as in the REPL
+pub struct Session {
+    checker: Checker,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        let mut checker = Checker::default();
+        for builtin in BUILTIN_TYPES {
+            let name = Symbol::new(builtin);
+            checker.data.insert(name, (name, 0));
+        }
+        Session { checker }
+    }
+
+    /// Checks a program, keeping its top-level definitions if it has no errors
+    pub fn check(&mut self, tree: &Tree) -> Result<Typed, Vec<TypeError>> {
+        let saved = self.checker.save();
+        self.checker.program(tree);
+
+        if !self.checker.errors.is_empty() {
+            self.checker.restore(saved);
+            return Err(std::mem::take(&mut self.checker.errors));
+        }
+
+        let mut typed = Typed::default();
+        for (ident, scheme) in std::mem::take(&mut self.checker.bindings) {
+            let ty = self.checker.resolve(&scheme.ty);
+            typed.bindings.push((ident, Scheme { ty, ..scheme }));
+        }
+        for (context, ty) in std::mem::take(&mut self.checker.names) {
+            typed.names.push((context, self.checker.resolve(&ty)));
+        }
+        Ok(typed)
+    }
+
+    /// The type of an expression, without keeping anything it defines
+    pub fn type_of(&mut self, tree: &Tree) -> Result<Scheme, Vec<TypeError>> {
+        let saved = self.checker.save();
+        self.checker.level += 1;
+        let ty = self.checker.program(tree);
+        self.checker.level -= 1;
+        let scheme = self.checker.generalize(&ty);
+
+        let errors = std::mem::take(&mut self.checker.errors);
+        self.checker.restore(saved);
+        match errors.is_empty() {
+            true => Ok(scheme),
+            false => Err(errors),
+        }
+    }
+
+    /// Runs `f`, then forgets the type variables it made; for checking
+    /// what isn't kept, such as a call from outside any program
+    pub fn scoped<T>(&mut self, f: impl FnOnce(&mut Session) -> T) -> T {
+        let vars = self.checker.vars.len();
+        let result = f(self);
+        self.checker.vars.truncate(vars);
+        result
+    }
+
+    /// The type of a top-level name, with fresh variables for its own
+    pub fn instantiate(&mut self, name: &str) -> Option<Type> {
+        let scheme = self.checker.lookup(Symbol::get(name)?)?.clone();
+        Some(self.checker.instantiate(&scheme))
+    }
+
+    /// The type of a constructor, a function unless it has no fields, with
+    /// fresh variables for its own
+    pub fn instantiate_constructor(&mut self, name: &str) -> Option<Type> {
+        let scheme = self.checker.cons.get(Symbol::get(name)?)?.clone();
+        Some(self.checker.instantiate(&scheme))
+    }
+
+    /// Unifies the type of a value from outside any program with the type
+    /// expected of it; `what` names where it's expected
+    pub fn expect_value(&mut self, found: &Type, expected: &Type, what: impl FnOnce() -> String) -> Result<(), Diagnostic> {
+        self.checker.expect_value(found, expected, what)
+    }
+
+    /// Shows a type with what's known of its variables so far
+    pub fn show(&self, ty: &Type) -> String {
+        Namer::default().show(&self.checker.resolve(ty))
+    }
+
+    /// Where the session is now, to go back to if a checked program's
+    /// definitions don't happen after all
+    pub fn save(&self) -> Saved {
+        self.checker.save()
+    }
+
+    pub fn restore(&mut self, saved: Saved) {
+        self.checker.restore(saved)
+    }
+}
+
+const BUILTIN_TYPES: [&str; 5] = ["Int", "Bool", "String", "Char", "Unit"];
+
+pub fn con(name: &str) -> Type {
+    Type::Con(Symbol::new(name), Vec::new())
+}
+
+enum Var {
+    Bound(Type),
+    /// Unbound, at the `let` nesting depth it was created in
+    Unbound(u32),
+}
+
+#[derive(Default)]
+struct Checker {
+    vars: Vec<Var>,
+    level: u32,
+
+    /// Names in scope, innermost last
+    env: Vec<(&'static Symbol, Scheme)>,
+    /// Declared types by name, with the type the name stands for and how
+    /// many parameters it takes
+    data: HashMap<&'static Symbol, (&'static Symbol, usize)>,
+    /// Constructor schemes, a function type unless the constructor has no fields
+    cons: HashMap<&'static Symbol, Scheme>,
+    /// Types and constructors the program being checked declares, which it
+    /// can't declare again
+    declared: HashSet<&'static Symbol>,
+    declared_cons: HashSet<&'static Symbol>,
+
+    bindings: Vec<(Ident, Scheme)>,
+    names: Vec<(Context, Type)>,
+    errors: Vec<TypeError>,
+}
+
+/// What a [`Session`] rolls back to when a check fails
+pub struct Saved {
+    env: usize,
+    data: HashMap<&'static Symbol, (&'static Symbol, usize)>,
+    cons: HashMap<&'static Symbol, Scheme>,
+}
+
+impl Checker {
+    fn save(&self) -> Saved {
+        Saved {
+            env: self.env.len(),
+            data: self.data.clone(),
+            cons: self.cons.clone(),
+        }
+    }
+
+    fn restore(&mut self, saved: Saved) {
+        self.env.truncate(saved.env);
+        self.data = saved.data;
+        self.cons = saved.cons;
+        self.bindings.clear();
+        self.names.clear();
+    }
+
+    /// Like [`Checker::infer`], but a top-level block's definitions stay in scope
+    fn program(&mut self, tree: &Tree) -> Type {
+        self.declared.clear();
+        self.declared_cons.clear();
+        match tree {
+            Tree::Block { items, .. } => {
+                let mut ty = con("Unit");
+                for item in items {
+                    ty = self.infer(item);
+                }
+                ty
+            }
+            tree => self.infer(tree),
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.vars.push(Var::Unbound(self.level));
+        Type::Var(self.vars.len() - 1)
+    }
+
+    /// Follows bound variables until reaching a structural type or a free variable
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(v) = ty {
+            match &self.vars[v] {
+                Var::Bound(bound) => ty = bound.clone(),
+                Var::Unbound(_) => break,
+            }
+        }
+        ty
+    }
+
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Con(name, args) => Type::Con(name, args.iter().map(|a| self.resolve(a)).collect()),
+            Type::Fn(params, ret) => Type::Fn(
+                params.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(&ret)),
+            ),
+            ty => ty,
+        }
+    }
+
+    /// Checks `var` doesn't occur in `ty`, pulling the variables of `ty` to
+    /// `var`'s level so they're generalized no sooner than it
+    fn occurs(&mut self, var: usize, level: u32, ty: &Type) -> bool {
+        match self.shallow(ty) {
+            Type::Var(v) if v == var => true,
+            Type::Var(v) => {
+                if let Var::Unbound(l) = self.vars[v] {
+                    self.vars[v] = Var::Unbound(l.min(level));
+                }
+                false
+            }
+            Type::Con(_, args) => args.iter().any(|a| self.occurs(var, level, a)),
+            Type::Fn(params, ret) => {
+                params.iter().any(|p| self.occurs(var, level, p)) || self.occurs(var, level, &ret)
+            }
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), TypeErrorKind> {
+        let (a, b) = (self.shallow(a), self.shallow(b));
+
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(v), other) | (other, Type::Var(v)) => {
+                let Var::Unbound(level) = self.vars[*v] else {
+                    unreachable!("shallow stops at unbound variables")
+                };
+                if self.occurs(*v, level, other) {
+                    let mut namer = Namer::default();
+                    let var = namer.name(*v);
+                    let ty = namer.show(&self.resolve(other));
+                    return Err(TypeErrorKind::InfiniteType { var, ty });
+                }
+                self.vars[*v] = Var::Bound(other.clone());
+                Ok(())
+            }
+            (Type::Con(x, xs), Type::Con(y, ys)) if x == y && xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(ys) {
+                    self.unify(x, y)?;
+                }
+                Ok(())
+            }
+            (Type::Fn(ps, r), Type::Fn(qs, s)) if ps.len() == qs.len() => {
+                for (p, q) in ps.iter().zip(qs) {
+                    self.unify(p, q)?;
+                }
+                self.unify(r, s)
+            }
+            _ => Err(self.mismatch(&a, &b)),
+        }
+    }
+
+    fn mismatch(&self, expected: &Type, found: &Type) -> TypeErrorKind {
+        let mut namer = Namer::default();
+        TypeErrorKind::Mismatch {
+            expected: namer.show(&self.resolve(expected)),
+            found: namer.show(&self.resolve(found)),
+        }
+    }
+
+    fn error(&mut self, kind: TypeErrorKind, context: Context) {
+        self.errors.push(TypeError {
+            kind,
+            context,
+            origin: None,
+        });
+    }
+
+    /// Unifies the type `found` at `context` with what it's `expected` to be.
+    /// Errors report the whole mismatched types rather than the inner parts
+    /// that failed to unify.
+    fn expect(&mut self, found: &Type, expected: &Type, context: Context) {
+        self.expect_from(found, expected, context, None);
+    }
+
+    fn expect_from(&mut self, found: &Type, expected: &Type, context: Context, origin: Option<(Context, String)>) {
+        let mismatch = self.mismatch(expected, found);
+        if let Err(kind) = self.unify(expected, found) {
+            let kind = match kind {
+                TypeErrorKind::Mismatch { .. } => mismatch,
+                kind => kind,
+            };
+            self.errors.push(TypeError {
+                kind,
+                context,
+                origin,
+            });
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh = scheme
+            .vars
+            .iter()
+            .map(|&v| (v, self.fresh()))
+            .collect::<HashMap<_, _>>();
+        self.substitute(&scheme.ty, &fresh)
+    }
+
+    fn substitute(&self, ty: &Type, map: &HashMap<usize, Type>) -> Type {
+        match self.shallow(ty) {
+            Type::Var(v) => map.get(&v).cloned().unwrap_or(Type::Var(v)),
+            Type::Con(name, args) => Type::Con(name, args.iter().map(|a| self.substitute(a, map)).collect()),
+            Type::Fn(params, ret) => Type::Fn(
+                params.iter().map(|p| self.substitute(p, map)).collect(),
+                Box::new(self.substitute(&ret, map)),
+            ),
+        }
+    }
+
+    /// Quantifies the variables created deeper than the current level
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut vars = Vec::new();
+        self.free_vars(&ty, &mut vars);
+        vars.retain(|&v| matches!(self.vars[v], Var::Unbound(l) if l > self.level));
+        Scheme { vars, ty }
+    }
+
+    fn free_vars(&self, ty: &Type, vars: &mut Vec<usize>) {
+        match ty {
+            Type::Var(v) if !vars.contains(v) => vars.push(*v),
+            Type::Var(_) => {}
+            Type::Con(_, args) => {
+                for a in args {
+                    self.free_vars(a, vars);
+                }
+            }
+            Type::Fn(params, ret) => {
+                for p in params {
+                    self.free_vars(p, vars);
+                }
+                self.free_vars(ret, vars);
+            }
+        }
+    }
+
+    fn lookup(&self, name: &'static Symbol) -> Option<&Scheme> {
+        self.env.iter().rev().find(|(n, _)| *n == name).map(|(_, s)| s)
+    }
+
+    /// Like [`Checker::expect`], for a value that has no context; `what`
+    /// names where it's expected
+    fn expect_value(&mut self, found: &Type, expected: &Type, what: impl FnOnce() -> String) -> Result<(), Diagnostic> {
+        // named before unifying, which may bind some of their variables
+        let mut namer = Namer::default();
+        let note = format!(
+            "{} expects {}, found {}",
+            what(),
+            namer.show(&self.resolve(expected)),
+            namer.show(&self.resolve(found))
+        );
+        match self.unify(expected, found) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(Diagnostic::error("mismatched types").with_code("E0200").with_note(note)),
+        }
+    }
+
+    fn data(&mut self, def: &DataDef) {
+        let name = def.name.name;
+        if BUILTIN_TYPES.contains(&name.name) || !self.declared.insert(name) {
+            self.error(TypeErrorKind::DuplicateType(name), def.name.context);
+            return;
+        }
+
+        // a type declared by an earlier program is shadowed by a new one,
+        // so code compiled against the old constructors can't be given
+        // values of the new ones
+        let ty = match self.data.contains_key(name) {
+            true => Symbol::fresh(name.name),
+            false => name,
+        };
+        // declared first, so constructors can refer to their own type
+        self.data.insert(name, (ty, def.params.len()));
+
+        let params = def
+            .params
+            .iter()
+            .map(|param| (param.name, self.fresh()))
+            .collect::<HashMap<_, _>>();
+        let vars = params
+            .values()
+            .map(|ty| match ty {
+                Type::Var(v) => *v,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let result = Type::Con(ty, def.params.iter().map(|p| params[p.name].clone()).collect());
+
+        for cons in &def.cons {
+            if !self.declared_cons.insert(cons.name.name) {
+                self.error(TypeErrorKind::DuplicateConstructor(cons.name.name), cons.name.context);
+                continue;
+            }
+            let fields = cons
+                .fields
+                .iter()
+                .map(|field| self.syntax_type(field, &params))
+                .collect::<Vec<_>>();
+
+            let ty = match fields.is_empty() {
+                true => result.clone(),
+                false => Type::Fn(fields, Box::new(result.clone())),
+            };
+
+            let scheme = Scheme {
+                vars: vars.clone(),
+                ty,
+            };
+            self.cons.insert(cons.name.name, scheme.clone());
+            self.env.push((cons.name.name, scheme));
+        }
+    }
+
+    /// Converts a type written in the source, where `params` are the type
+    /// variables in scope
+    fn syntax_type(&mut self, ty: &s0::Type, params: &HashMap<&'static Symbol, Type>) -> Type {
+        match ty {
+            s0::Type::Var(name) => match params.get(name.name) {
+                Some(ty) => ty.clone(),
+                None => {
+                    self.error(TypeErrorKind::UnboundTypeVar(name.name), name.context);
+                    self.fresh()
+                }
+            },
+            s0::Type::Named { name, args, context } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.syntax_type(arg, params))
+                    .collect::<Vec<_>>();
+
+                match self.data.get(name.name) {
+                    None => self.error(TypeErrorKind::UnknownType(name.name), name.context),
+                    Some(&(_, arity)) if arity != args.len() => {
+                        let kind = TypeErrorKind::TypeArity {
+                            name: name.name,
+                            expected: arity,
+                            found: args.len(),
+                        };
+                        self.error(kind, *context);
+                    }
+                    Some(&(ty, _)) => return Type::Con(ty, args),
+                }
+                Type::Con(name.name, args)
+            }
+        }
+    }
+
+    fn infer(&mut self, tree: &Tree) -> Type {
+        match tree {
+            Tree::Block { items, .. } => {
+                let scope = self.env.len();
+                let mut ty = con("Unit");
+                for item in items {
+                    ty = self.infer(item);
+                }
+                self.env.truncate(scope);
+                ty
+            }
+            Tree::Data(def) => {
+                self.data(def);
+                con("Unit")
+            }
+            Tree::Let { name, value, .. } => {
+                self.level += 1;
+                let ty = self.fresh();
+
+                // functions may call themselves
+                let recursive = matches!(**value, Tree::Fn { .. });
+                if recursive {
+                    self.env.push((name.name, Scheme::mono(ty.clone())));
+                }
+
+                let value_ty = self.infer(value);
+                self.expect(&value_ty, &ty, value.context());
+
+                if recursive {
+                    self.env.pop();
+                }
+                self.level -= 1;
+
+                let scheme = self.generalize(&ty);
+                self.bindings.push((*name, scheme.clone()));
+                self.env.push((name.name, scheme));
+                con("Unit")
+            }
+            Tree::Fn { params, body, .. } => {
+                let scope = self.env.len();
+                let param_tys = params
+                    .iter()
+                    .map(|param| {
+                        let ty = self.fresh();
+                        self.names.push((param.context, ty.clone()));
+                        self.env.push((param.name, Scheme::mono(ty.clone())));
+                        ty
+                    })
+                    .collect();
+
+                let ret = self.infer(body);
+                self.env.truncate(scope);
+                Type::Fn(param_tys, Box::new(ret))
+            }
+            Tree::Match {
+                scrutinee, arms, ..
+            } => {
+                let scrutinee_ty = self.infer(scrutinee);
+                let result = self.fresh();
+                let mut first: Option<Context> = None;
+
+                for arm in arms {
+                    let scope = self.env.len();
+                    let pattern_ty = self.pattern(&arm.pattern);
+                    self.expect_from(
+                        &pattern_ty,
+                        &scrutinee_ty,
+                        arm.pattern.context(),
+                        Some((scrutinee.context(), "matching on this".to_string())),
+                    );
+
+                    let body_ty = self.infer(&arm.body);
+                    let origin = first.map(|c| (c, "the first arm's type".to_string()));
+                    self.expect_from(&body_ty, &result, arm.body.context(), origin);
+                    first = first.or(Some(arm.body.context()));
+
+                    self.env.truncate(scope);
+                }
+
+                result
+            }
+            Tree::If {
+                cond, then, els, ..
+            } => {
+                let cond_ty = self.infer(cond);
+                self.expect(&cond_ty, &con("Bool"), cond.context());
+
+                let then_ty = self.infer(then);
+                let els_ty = self.infer(els);
+                let origin = Some((then.context(), "the `if` branch's type".to_string()));
+                self.expect_from(&els_ty, &then_ty, els.context(), origin);
+                then_ty
+            }
+            Tree::Call {
+                func,
+                args,
+                context,
+            } => {
+                let func_ty = self.infer(func);
+                let arg_tys = args.iter().map(|arg| self.infer(arg)).collect::<Vec<_>>();
+                let ret = self.fresh();
+
+                match self.shallow(&func_ty) {
+                    Type::Fn(params, ret) => {
+                        if params.len() != arg_tys.len() {
+                            let kind = TypeErrorKind::ArgCount {
+                                expected: params.len(),
+                                found: arg_tys.len(),
+                            };
+                            self.error(kind, *context);
+                            return self.fresh();
+                        }
+
+                        for ((param, arg_ty), arg) in params.iter().zip(&arg_tys).zip(args) {
+                            self.expect(arg_ty, param, arg.context());
+                        }
+                        *ret
+                    }
+                    _ => {
+                        let expected = Type::Fn(arg_tys, Box::new(ret.clone()));
+                        self.expect(&func_ty, &expected, func.context());
+                        ret
+                    }
+                }
+            }
+            Tree::Binary { op, lhs, rhs, .. } => {
+                use BinOp::*;
+                let (operand, result) = match op {
+                    Add | Sub | Mul | Div | Rem => (con("Int"), con("Int")),
+                    Lt | Le | Gt | Ge => (con("Int"), con("Bool")),
+                    Eq | NotEq => (self.fresh(), con("Bool")),
+                    And | Or => (con("Bool"), con("Bool")),
+                };
+
+                let lhs_ty = self.infer(lhs);
+                self.expect(&lhs_ty, &operand, lhs.context());
+                let rhs_ty = self.infer(rhs);
+                self.expect(&rhs_ty, &operand, rhs.context());
+                result
+            }
+            Tree::Unary { op, operand, .. } => {
+                let ty = match op {
+                    UnOp::Neg => con("Int"),
+                    UnOp::Not => con("Bool"),
+                };
+                let operand_ty = self.infer(operand);
+                self.expect(&operand_ty, &ty, operand.context());
+                ty
+            }
+            Tree::Var(ident) => match self.lookup(ident.name).cloned() {
+                Some(scheme) => {
+                    let ty = self.instantiate(&scheme);
+                    self.names.push((ident.context, ty.clone()));
+                    ty
+                }
+                None => {
+                    self.error(TypeErrorKind::Undefined(ident.name), ident.context);
+                    self.fresh()
+                }
+            },
+            Tree::IntLit { .. } => con("Int"),
+            Tree::BoolLit { .. } => con("Bool"),
+            Tree::StrLit { .. } => con("String"),
+            Tree::CharLit { .. } => con("Char"),
+            // anything goes where the program couldn't be parsed
+            Tree::Error(_) => self.fresh(),
+        }
+    }
+
+    /// Infers a pattern's type, binding its variables
+    fn pattern(&mut self, pattern: &Pattern) -> Type {
+        match pattern {
+            Pattern::Wildcard(_) => self.fresh(),
+            Pattern::Bind(ident) => {
+                let ty = self.fresh();
+                self.names.push((ident.context, ty.clone()));
+                self.env.push((ident.name, Scheme::mono(ty.clone())));
+                ty
+            }
+            Pattern::Cons { name, args, context } => {
+                let Some(scheme) = self.cons.get(name.name).cloned() else {
+                    self.error(TypeErrorKind::UnknownConstructor(name.name), name.context);
+                    for arg in args {
+                        self.pattern(arg);
+                    }
+                    return self.fresh();
+                };
+
+                let (fields, result) = match self.instantiate(&scheme) {
+                    Type::Fn(fields, result) => (fields, *result),
+                    result => (Vec::new(), result),
+                };
+
+                if fields.len() != args.len() {
+                    let kind = TypeErrorKind::ConsArity {
+                        name: name.name,
+                        expected: fields.len(),
+                        found: args.len(),
+                    };
+                    self.error(kind, *context);
+                }
+
+                for (field, arg) in fields.iter().zip(args) {
+                    let arg_ty = self.pattern(arg);
+                    self.expect(&arg_ty, field, arg.context());
+                }
+                result
+            }
+            Pattern::IntLit { .. } => con("Int"),
+            Pattern::BoolLit { .. } => con("Bool"),
+            Pattern::StrLit { .. } => con("String"),
+            Pattern::CharLit { .. } => con("Char"),
+        }
+    }
+}
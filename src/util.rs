@@ -1,12 +1,9 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 
 use lazy_static::lazy_static;
 
-pub fn leak(s: &str) -> &'static str {
-    Box::leak(s.to_string().into_boxed_str())
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Symbol {
     pub name: &'static str,
@@ -14,37 +11,40 @@ pub struct Symbol {
 }
 
 lazy_static! {
-    static ref SYMBOLS: Mutex<Vec<String>> = Mutex::new(vec![]);
-    static ref SYMBOLS_MAP: Mutex<HashMap<&'static str, Symbol>> = Mutex::new(HashMap::new());
+    // symbols are leaked, since they're never removed and must not move
+    static ref SYMBOLS_MAP: Mutex<HashMap<&'static str, &'static Symbol>> = Mutex::new(HashMap::new());
 }
 
-fn static_symbol(symbol: &Symbol) -> &'static Symbol {
-    // safe because we never remove symbols from the map
-    unsafe { std::mem::transmute::<&Symbol, &'static Symbol>(symbol) }
-}
+/// How many symbols were made, counting those not in the map
+static SYMBOLS: AtomicUsize = AtomicUsize::new(0);
 
 impl Symbol {
     pub fn new(s: &str) -> &'static Symbol {
-        let mut symbols = SYMBOLS.lock().unwrap();
         let mut symbols_map = SYMBOLS_MAP.lock().unwrap();
 
-        if let Some(ref symbol) = symbols_map.get(s) {
-            return static_symbol(symbol);
+        if let Some(symbol) = symbols_map.get(s) {
+            return symbol;
         }
 
-        let name = s.to_string();
-        let index = symbols.len();
+        let name: &'static str = Box::leak(s.into());
+        let index = SYMBOLS.fetch_add(1, Ordering::Relaxed);
 
-        symbols.push(name);
-        let name = symbols.last().unwrap().as_str();
+        let symbol: &'static Symbol = Box::leak(Box::new(Symbol { name, index }));
+        symbols_map.insert(name, symbol);
 
-        // safe because we never remove symbols from the vec
-        let name = unsafe { std::mem::transmute::<&str, &'static str>(name) };
+        symbol
+    }
 
-        let symbol = Symbol { name, index };
-        symbols_map.insert(name, symbol);
-        let symbol = symbols_map.get(name).unwrap();
+    /// A symbol named `s` but equal to no other, for something declared
+    /// again under a name that's taken
+    pub fn fresh(s: &str) -> &'static Symbol {
+        let name = Symbol::new(s).name;
+        let index = SYMBOLS.fetch_add(1, Ordering::Relaxed);
+        Box::leak(Box::new(Symbol { name, index }))
+    }
 
-        static_symbol(symbol)
+    /// The symbol for `s` if it was ever made, without making one
+    pub fn get(s: &str) -> Option<&'static Symbol> {
+        SYMBOLS_MAP.lock().unwrap().get(s).copied()
     }
 }
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{ast::s0::*, context::Context, decision::Case, util::Symbol};
+
+use super::value::Value;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    /// Pushes a constant from the chunk's pool
+    Constant(u32),
+    Unit,
+    True,
+    False,
+    Pop,
+    /// Pops `n` values from under the top of the stack, keeping the top
+    Slide(u32),
+    /// Pushes a local, by its slot in the current frame
+    GetLocal(u32),
+    GetCapture(u32),
+    /// Pushes the running function, for recursive calls
+    Current,
+    GetGlobal(u32),
+    /// Pops a value into a global
+    DefineGlobal(u32),
+    /// Replaces a data value with one of its fields
+    Field(u32),
+    /// Replaces a value with whether it matches the chunk's case
+    Test(u32),
+    /// Fails on the value on top, which no match arm matches
+    NoMatch,
+    /// Fails where the program has a syntax error
+    Unparsed,
+    /// Makes a closure of the chunk's function
+    Closure(u32),
+    /// Calls the function under `n` arguments
+    Call(u32),
+    /// Calls a function whose result the current one returns, replacing
+    /// the current frame
+    TailCall(u32),
+    Return,
+    Jump(u32),
+    /// Pops a Bool, jumping if it's false
+    JumpIfFalse(u32),
+    Binary(BinOp),
+    Unary(UnOp),
+}
+
+/// Where a closure gets a captured value from, in the enclosing function
+#[derive(Debug, Clone, Copy)]
+pub enum Capture {
+    Local(u32),
+    /// One of the enclosing function's own captures
+    Outer(u32),
+    Current,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// The source of each instruction, for errors
+    pub contexts: Vec<Context>,
+    pub constants: Vec<Value>,
+    pub cases: Vec<Case>,
+    pub functions: Vec<Rc<Proto>>,
+}
+
+/// A compiled function, which closures are made from
+#[derive(Debug)]
+pub struct Proto {
+    pub name: Option<&'static Symbol>,
+    pub arity: usize,
+    pub captures: Vec<Capture>,
+    pub chunk: Chunk,
+}
+
+/// Global variables, numbered in the order they're first mentioned
+#[derive(Debug, Default)]
+pub struct Globals {
+    pub names: Vec<&'static Symbol>,
+    indices: HashMap<&'static Symbol, u32>,
+}
+
+impl Globals {
+    pub fn index(&mut self, name: &'static Symbol) -> u32 {
+        *self.indices.entry(name).or_insert_with(|| {
+            self.names.push(name);
+            self.names.len() as u32 - 1
+        })
+    }
+
+    /// The index of a global, unless it was never mentioned
+    pub fn get(&self, name: &'static Symbol) -> Option<u32> {
+        self.indices.get(name).copied()
+    }
+}
+
+impl Display for Case {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Case::Cons { name, arity } => write!(f, "{}/{}", name.name, arity),
+            Case::Int(n) => write!(f, "{}", n),
+            Case::Bool(b) => write!(f, "{}", b),
+            Case::Str(s) => write!(f, "{:?}", s),
+            Case::Char(c) => write!(f, "{:?}", c),
+        }
+    }
+}
+
+/// Prints a function and the functions inside it, one instruction per line
+pub struct Disassembly<'a> {
+    pub proto: &'a Proto,
+    pub globals: &'a Globals,
+}
+
+impl Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Proto {
+            name,
+            arity,
+            captures,
+            chunk,
+        } = self.proto;
+
+        match name {
+            Some(name) => writeln!(f, "== {} ({} params) ==", name.name, arity)?,
+            None => writeln!(f, "== <fn> ({} params) ==", arity)?,
+        }
+        for (i, capture) in captures.iter().enumerate() {
+            writeln!(f, "capture {} <- {:?}", i, capture)?;
+        }
+
+        let mut last_line = 0;
+        for (offset, (op, context)) in chunk.code.iter().zip(&chunk.contexts).enumerate() {
+            let file = crate::source::get(context.file);
+            let (line, _) = file.line_col(context.start);
+            match line == last_line {
+                true => write!(f, "{:04}    | ", offset)?,
+                false => write!(f, "{:04} {:4} ", offset, line)?,
+            }
+            last_line = line;
+
+            let text = format!("{:?}", op).replace('(', " ").replace(')', "");
+            let note = match op {
+                Op::Constant(i) => chunk.constants[*i as usize].to_string(),
+                Op::GetGlobal(i) | Op::DefineGlobal(i) => self.globals.names[*i as usize].name.to_string(),
+                Op::Test(i) => chunk.cases[*i as usize].to_string(),
+                Op::Closure(i) => match chunk.functions[*i as usize].name {
+                    Some(name) => name.name.to_string(),
+                    None => "<fn>".to_string(),
+                },
+                _ => {
+                    writeln!(f, "{}", text)?;
+                    continue;
+                }
+            };
+            writeln!(f, "{:<20} ; {}", text, note)?;
+        }
+
+        for proto in &chunk.functions {
+            writeln!(f)?;
+            write!(
+                f,
+                "{}",
+                Disassembly {
+                    proto,
+                    globals: self.globals
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
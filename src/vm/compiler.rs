@@ -0,0 +1,456 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::s0::*,
+    context::Context,
+    decision::{self, Constructors, Decision, Path},
+    util::Symbol,
+};
+
+use super::bytecode::*;
+use super::value::Value;
+
+/// Compiles a program into a function of no parameters which runs it;
+/// top-level `let`s become globals, and top-level `data` adds to
+/// `constructors`
+pub fn compile(tree: &Tree, globals: &mut Globals, constructors: &mut Constructors) -> Proto {
+    let mut compiler = Compiler {
+        globals,
+        constructors,
+        functions: vec![Function::new(Some(Symbol::new("<program>")), &[])],
+    };
+
+    match tree {
+        Tree::Block { items, context } => compiler.global_block(items, *context),
+        tree => compiler.expr(tree),
+    }
+    compiler.emit(Op::Return, tree.context());
+
+    compiler.functions.pop().unwrap().finish()
+}
+
+/// A function being compiled
+struct Function {
+    name: Option<&'static Symbol>,
+    arity: usize,
+    /// Variables on the stack, by slot in the frame
+    locals: Vec<(&'static Symbol, u32)>,
+    captures: Vec<(&'static Symbol, Capture)>,
+    chunk: Chunk,
+    /// How many values the frame holds at the current instruction
+    height: u32,
+}
+
+impl Function {
+    fn new(name: Option<&'static Symbol>, params: &[Ident]) -> Function {
+        Function {
+            name,
+            arity: params.len(),
+            locals: params.iter().zip(0..).map(|(p, slot)| (p.name, slot)).collect(),
+            captures: Vec::new(),
+            chunk: Chunk::default(),
+            height: params.len() as u32,
+        }
+    }
+
+    fn finish(self) -> Proto {
+        Proto {
+            name: self.name,
+            arity: self.arity,
+            captures: self.captures.into_iter().map(|(_, c)| c).collect(),
+            chunk: self.chunk,
+        }
+    }
+}
+
+struct Compiler<'a> {
+    globals: &'a mut Globals,
+    /// The `data` types in scope, so matches covering one need no default
+    constructors: &'a mut Constructors,
+    /// The functions being compiled, innermost last
+    functions: Vec<Function>,
+}
+
+impl Compiler<'_> {
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op, context: Context) -> usize {
+        let f = self.function();
+        f.height = match op {
+            Op::Constant(_)
+            | Op::Unit
+            | Op::True
+            | Op::False
+            | Op::GetLocal(_)
+            | Op::GetCapture(_)
+            | Op::Current
+            | Op::GetGlobal(_)
+            | Op::Closure(_)
+            | Op::Unparsed => f.height + 1,
+            Op::Pop | Op::DefineGlobal(_) | Op::JumpIfFalse(_) | Op::Return | Op::Binary(_) => f.height - 1,
+            Op::Slide(n) | Op::Call(n) | Op::TailCall(n) => f.height - n,
+            Op::Field(_) | Op::Test(_) | Op::NoMatch | Op::Jump(_) | Op::Unary(_) => f.height,
+        };
+        f.chunk.code.push(op);
+        f.chunk.contexts.push(context);
+        f.chunk.code.len() - 1
+    }
+
+    fn constant(&mut self, value: Value, context: Context) {
+        let constants = &mut self.function().chunk.constants;
+        constants.push(value);
+        let index = constants.len() as u32 - 1;
+        self.emit(Op::Constant(index), context);
+    }
+
+    /// Points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let code = &mut self.function().chunk.code;
+        let target = code.len() as u32;
+        match &mut code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn height(&self) -> u32 {
+        self.functions.last().unwrap().height
+    }
+
+    fn set_height(&mut self, height: u32) {
+        self.function().height = height;
+    }
+
+    /// Names the value on top of the stack
+    fn declare(&mut self, name: &'static Symbol) {
+        let f = self.function();
+        f.locals.push((name, f.height - 1));
+    }
+
+    /// Finds a variable in function `depth`, capturing it from enclosing
+    /// functions if needed; `None` means it's global
+    fn resolve(&mut self, depth: usize, name: &'static Symbol) -> Option<Capture> {
+        let f = &self.functions[depth];
+        if let Some(&(_, slot)) = f.locals.iter().rev().find(|(n, _)| *n == name) {
+            return Some(Capture::Local(slot));
+        }
+        if f.name == Some(name) {
+            return Some(Capture::Current);
+        }
+        if let Some(i) = f.captures.iter().position(|(n, _)| *n == name) {
+            return Some(Capture::Outer(i as u32));
+        }
+        if depth == 0 {
+            return None;
+        }
+
+        let outer = self.resolve(depth - 1, name)?;
+        let captures = &mut self.functions[depth].captures;
+        captures.push((name, outer));
+        Some(Capture::Outer(captures.len() as u32 - 1))
+    }
+
+    fn constructors(&mut self, def: &DataDef, mut bind: impl FnMut(&mut Self, &'static Symbol, Context)) {
+        decision::declare(self.constructors, def);
+        for cons in &def.cons {
+            let name = cons.name.name;
+            let arity = cons.fields.len();
+            self.constant(Value::Constructor { name, arity }, cons.context);
+            // values without fields are made once, up front
+            if arity == 0 {
+                self.emit(Op::Call(0), cons.context);
+            }
+            bind(self, name, cons.context);
+        }
+    }
+
+    /// The top-level block, whose `let`s and `data` define globals
+    fn global_block(&mut self, items: &[Tree], context: Context) {
+        let mut value = false;
+        for (i, item) in items.iter().enumerate() {
+            value = false;
+            match item {
+                Tree::Let { name, value: v, .. } => {
+                    self.let_value(name.name, v);
+                    let index = self.globals.index(name.name);
+                    self.emit(Op::DefineGlobal(index), item.context());
+                }
+                Tree::Data(def) => self.constructors(def, |this, name, context| {
+                    let index = this.globals.index(name);
+                    this.emit(Op::DefineGlobal(index), context);
+                }),
+                item => {
+                    self.expr(item);
+                    value = true;
+                    if i + 1 < items.len() {
+                        self.emit(Op::Pop, item.context());
+                    }
+                }
+            }
+        }
+        if !value {
+            self.emit(Op::Unit, context);
+        }
+    }
+
+    fn let_value(&mut self, name: &'static Symbol, value: &Tree) {
+        match value {
+            Tree::Fn { params, body, context } => self.func(Some(name), params, body, *context),
+            value => self.expr(value),
+        }
+    }
+
+    fn func(&mut self, name: Option<&'static Symbol>, params: &[Ident], body: &Tree, context: Context) {
+        self.functions.push(Function::new(name, params));
+        self.expr_at(body, true);
+        self.emit(Op::Return, body.context());
+        let proto = self.functions.pop().unwrap().finish();
+
+        let functions = &mut self.function().chunk.functions;
+        functions.push(Rc::new(proto));
+        let index = functions.len() as u32 - 1;
+        self.emit(Op::Closure(index), context);
+    }
+
+    fn expr(&mut self, tree: &Tree) {
+        self.expr_at(tree, false)
+    }
+
+    /// `tail` is whether the function returns the value straight away, so a
+    /// call there can reuse the function's frame
+    fn expr_at(&mut self, tree: &Tree, tail: bool) {
+        match tree {
+            Tree::Block { items, context } => {
+                let scope = self.function().locals.len();
+                // a type declared here may hide constructors outside the block
+                let outer = items
+                    .iter()
+                    .any(|item| matches!(item, Tree::Data(_)))
+                    .then(|| self.constructors.clone());
+                let mut value = false;
+                for (i, item) in items.iter().enumerate() {
+                    value = false;
+                    match item {
+                        Tree::Let { name, value: v, .. } => {
+                            self.let_value(name.name, v);
+                            self.declare(name.name);
+                        }
+                        Tree::Data(def) => self.constructors(def, |this, name, _| this.declare(name)),
+                        item => {
+                            let last = i + 1 == items.len();
+                            self.expr_at(item, tail && last);
+                            value = true;
+                            if !last {
+                                self.emit(Op::Pop, item.context());
+                            }
+                        }
+                    }
+                }
+                if !value {
+                    self.emit(Op::Unit, *context);
+                }
+
+                if let Some(outer) = outer {
+                    *self.constructors = outer;
+                }
+                let f = self.function();
+                let locals = (f.locals.len() - scope) as u32;
+                f.locals.truncate(scope);
+                if locals > 0 {
+                    self.emit(Op::Slide(locals), *context);
+                }
+            }
+            // outside a block, nothing can see what these define
+            Tree::Data(def) => {
+                self.emit(Op::Unit, def.context);
+            }
+            Tree::Let { name, value, context } => {
+                self.let_value(name.name, value);
+                self.emit(Op::Pop, *context);
+                self.emit(Op::Unit, *context);
+            }
+            Tree::Fn { params, body, context } => self.func(None, params, body, *context),
+            Tree::Match {
+                scrutinee,
+                arms,
+                context,
+            } => {
+                self.expr(scrutinee);
+                let slot = self.height() - 1;
+
+                let decision = decision::compile(arms, self.constructors);
+                let mut ends = Vec::new();
+                self.decision(&decision, slot, arms, *context, tail, &mut ends);
+                for end in ends {
+                    self.patch(end);
+                }
+
+                self.set_height(slot + 2);
+                self.emit(Op::Slide(1), *context);
+            }
+            Tree::If {
+                cond,
+                then,
+                els,
+                context,
+            } => {
+                self.expr(cond);
+                let to_else = self.emit(Op::JumpIfFalse(0), cond.context());
+                self.expr_at(then, tail);
+                let to_end = self.emit(Op::Jump(0), *context);
+                self.patch(to_else);
+                self.set_height(self.height() - 1);
+                self.expr_at(els, tail);
+                self.patch(to_end);
+            }
+            Tree::Call {
+                func,
+                args,
+                context,
+            } => {
+                self.expr(func);
+                for arg in args {
+                    self.expr(arg);
+                }
+                let argc = args.len() as u32;
+                let op = if tail { Op::TailCall(argc) } else { Op::Call(argc) };
+                self.emit(op, *context);
+            }
+            Tree::Binary {
+                op: op @ (BinOp::And | BinOp::Or),
+                lhs,
+                rhs,
+                context,
+            } => {
+                // `&&` and `||` only evaluate their right side when needed
+                self.expr(lhs);
+                let to_short = self.emit(Op::JumpIfFalse(0), *context);
+                match op {
+                    BinOp::And => self.expr_at(rhs, tail),
+                    _ => {
+                        self.emit(Op::True, *context);
+                    }
+                }
+                let to_end = self.emit(Op::Jump(0), *context);
+                self.patch(to_short);
+                self.set_height(self.height() - 1);
+                match op {
+                    BinOp::And => {
+                        self.emit(Op::False, *context);
+                    }
+                    _ => self.expr_at(rhs, tail),
+                }
+                self.patch(to_end);
+            }
+            Tree::Binary {
+                op,
+                lhs,
+                rhs,
+                context,
+            } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit(Op::Binary(*op), *context);
+            }
+            Tree::Unary {
+                op,
+                operand,
+                context,
+            } => {
+                self.expr(operand);
+                self.emit(Op::Unary(*op), *context);
+            }
+            Tree::Var(ident) => {
+                let depth = self.functions.len() - 1;
+                let op = match self.resolve(depth, ident.name) {
+                    Some(Capture::Local(slot)) => Op::GetLocal(slot),
+                    Some(Capture::Outer(i)) => Op::GetCapture(i),
+                    Some(Capture::Current) => Op::Current,
+                    None => Op::GetGlobal(self.globals.index(ident.name)),
+                };
+                self.emit(op, ident.context);
+            }
+            Tree::IntLit { value, context } => self.constant(Value::Int(*value), *context),
+            Tree::BoolLit { value, context } => {
+                let op = if *value { Op::True } else { Op::False };
+                self.emit(op, *context);
+            }
+            Tree::StrLit { value, context } => self.constant(Value::Str(value.clone()), *context),
+            Tree::CharLit { value, context } => self.constant(Value::Char(*value), *context),
+            // fails when reached, so a recovered program runs up to its
+            // first syntax error
+            Tree::Error(context) => {
+                self.emit(Op::Unparsed, *context);
+            }
+        }
+    }
+
+    /// Pushes the part of the scrutinee at `path`
+    fn load(&mut self, slot: u32, path: &Path, context: Context) {
+        self.emit(Op::GetLocal(slot), context);
+        for &i in path {
+            self.emit(Op::Field(i as u32), context);
+        }
+    }
+
+    /// Emits `decision` for the scrutinee in `slot`; every arm leaves its
+    /// value on top and jumps to one of `ends`
+    fn decision(
+        &mut self,
+        decision: &Decision,
+        slot: u32,
+        arms: &[MatchArm],
+        context: Context,
+        tail: bool,
+        ends: &mut Vec<usize>,
+    ) {
+        let height = self.height();
+        match decision {
+            Decision::Fail => {
+                self.emit(Op::GetLocal(slot), context);
+                self.emit(Op::NoMatch, context);
+            }
+            Decision::Leaf { arm, bindings } => {
+                let arm = &arms[*arm];
+                let scope = self.function().locals.len();
+                for (name, path) in bindings {
+                    self.load(slot, path, arm.pattern.context());
+                    self.declare(name);
+                }
+                self.expr_at(&arm.body, tail);
+
+                self.function().locals.truncate(scope);
+                if !bindings.is_empty() {
+                    self.emit(Op::Slide(bindings.len() as u32), arm.context);
+                }
+                ends.push(self.emit(Op::Jump(0), arm.context));
+            }
+            Decision::Switch { path, cases, default } => {
+                for (i, (case, next)) in cases.iter().enumerate() {
+                    // with no default, the last case can't fail to match
+                    let last = default.is_none() && i + 1 == cases.len();
+                    if last {
+                        self.decision(next, slot, arms, context, tail, ends);
+                        break;
+                    }
+
+                    self.load(slot, path, context);
+                    let cases = &mut self.function().chunk.cases;
+                    cases.push(case.clone());
+                    let index = cases.len() as u32 - 1;
+                    self.emit(Op::Test(index), context);
+                    let to_next = self.emit(Op::JumpIfFalse(0), context);
+                    self.decision(next, slot, arms, context, tail, ends);
+                    self.patch(to_next);
+                    self.set_height(height);
+                }
+                if let Some(default) = default {
+                    self.decision(default, slot, arms, context, tail, ends);
+                }
+            }
+        }
+        self.set_height(height);
+    }
+}
@@ -0,0 +1,230 @@
+//! A mark-sweep garbage-collected heap for data values and closures
+
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::util::Symbol;
+
+use super::bytecode::Proto;
+use super::value::Value;
+
+/// A handle to an object on the heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gc(u32);
+
+#[derive(Debug)]
+pub struct Data {
+    pub cons: &'static Symbol,
+    pub fields: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub proto: Rc<Proto>,
+    /// Values of the variables the function uses from enclosing functions
+    pub captures: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub enum Object {
+    Data(Data),
+    Closure(Closure),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub collections: usize,
+    pub allocated: usize,
+    pub freed: usize,
+    pub peak: usize,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gc: {} collections, {} objects allocated, {} freed, {} live, {} at peak",
+            self.collections,
+            self.allocated,
+            self.freed,
+            self.allocated - self.freed,
+            self.peak
+        )
+    }
+}
+
+/// Collections start once this many objects are live
+const INITIAL_THRESHOLD: usize = 1024;
+
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    marks: Vec<bool>,
+    /// Empty slots in `objects`
+    free: Vec<u32>,
+    threshold: usize,
+    /// Collect before every allocation, to catch values that aren't rooted
+    pub stress: bool,
+    pub stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap {
+            objects: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            threshold: INITIAL_THRESHOLD,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+impl Heap {
+    pub fn live(&self) -> usize {
+        self.stats.allocated - self.stats.freed
+    }
+
+    /// Whether to collect before the next allocation
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.live() >= self.threshold
+    }
+
+    /// Allocates without collecting; the caller collects first if
+    /// [`Heap::should_collect`], while everything it needs is rooted
+    pub fn alloc(&mut self, object: Object) -> Gc {
+        self.stats.allocated += 1;
+        self.stats.peak = self.stats.peak.max(self.live());
+
+        match self.free.pop() {
+            Some(i) => {
+                self.objects[i as usize] = Some(object);
+                Gc(i)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                Gc(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+
+    fn get(&self, gc: Gc) -> &Object {
+        self.objects[gc.0 as usize].as_ref().expect("use of a collected object")
+    }
+
+    pub fn data(&self, gc: Gc) -> &Data {
+        match self.get(gc) {
+            Object::Data(data) => data,
+            Object::Closure(_) => unreachable!("expected a data value"),
+        }
+    }
+
+    pub fn closure(&self, gc: Gc) -> &Closure {
+        match self.get(gc) {
+            Object::Closure(closure) => closure,
+            Object::Data(_) => unreachable!("expected a closure"),
+        }
+    }
+
+    /// Frees everything not reachable from `roots`
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Value>) {
+        let mut work = Vec::new();
+        for root in roots {
+            if let Value::Data(gc) | Value::Closure(gc) = root {
+                work.push(gc);
+            }
+        }
+
+        // marking uses a work list, since lists can be very long
+        while let Some(gc) = work.pop() {
+            let mark = &mut self.marks[gc.0 as usize];
+            if *mark {
+                continue;
+            }
+            *mark = true;
+
+            let children = match self.get(gc) {
+                Object::Data(data) => &data.fields,
+                Object::Closure(closure) => &closure.captures,
+            };
+            for child in children {
+                if let Value::Data(gc) | Value::Closure(gc) = child {
+                    work.push(*gc);
+                }
+            }
+        }
+
+        for (i, (object, mark)) in self.objects.iter_mut().zip(&mut self.marks).enumerate() {
+            if *mark {
+                *mark = false;
+            } else if object.take().is_some() {
+                self.free.push(i as u32);
+                self.stats.freed += 1;
+            }
+        }
+
+        self.stats.collections += 1;
+        self.threshold = INITIAL_THRESHOLD.max(self.live() * 2);
+    }
+
+    /// Renders a value with the data it refers to
+    pub fn show(&self, value: &Value) -> String {
+        enum Item<'a> {
+            Value(&'a Value),
+            Text(&'static str),
+        }
+
+        let mut out = String::new();
+        let mut work = vec![Item::Value(value)];
+        while let Some(item) = work.pop() {
+            match item {
+                Item::Text(text) => out += text,
+                Item::Value(Value::Data(gc)) => {
+                    let data = self.data(*gc);
+                    out += data.cons.name;
+                    if data.fields.is_empty() {
+                        continue;
+                    }
+
+                    // pushed in reverse, to come off in order
+                    work.push(Item::Text(")"));
+                    for (i, field) in data.fields.iter().enumerate().rev() {
+                        work.push(Item::Value(field));
+                        if i > 0 {
+                            work.push(Item::Text(", "));
+                        }
+                    }
+                    work.push(Item::Text("("));
+                }
+                Item::Value(value) => out += &value.to_string(),
+            }
+        }
+        out
+    }
+
+    /// Structural equality, `None` if functions had to be compared
+    pub fn equals(&self, a: &Value, b: &Value) -> Option<bool> {
+        let mut work = vec![(a, b)];
+        while let Some((a, b)) = work.pop() {
+            let eq = match (a, b) {
+                (Value::Unit, Value::Unit) => true,
+                (Value::Int(a), Value::Int(b)) => a == b,
+                (Value::Bool(a), Value::Bool(b)) => a == b,
+                (Value::Str(a), Value::Str(b)) => a == b,
+                (Value::Char(a), Value::Char(b)) => a == b,
+                (Value::Data(a), Value::Data(b)) => {
+                    let (a, b) = (self.data(*a), self.data(*b));
+                    work.extend(a.fields.iter().zip(&b.fields));
+                    a.cons == b.cons
+                }
+                (Value::Constructor { .. } | Value::Closure(_), _) => return None,
+                _ => false,
+            };
+            if !eq {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+}
@@ -0,0 +1,408 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::s0::*,
+    context::Context,
+    decision::{Case, Constructors},
+    util::Symbol,
+};
+
+use super::bytecode::*;
+use super::heap::*;
+use super::value::*;
+use RuntimeErrorKind::*;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Calls deeper than this are runaway recursion
+pub(super) const MAX_FRAMES: usize = 1_000_000;
+
+struct Frame {
+    closure: Gc,
+    /// The closure's function, kept here to reach its code without the heap
+    proto: Rc<Proto>,
+    ip: usize,
+    /// Where the frame's slots start on the stack, just above the callee
+    base: usize,
+}
+
+/// Runs compiled code; globals outlive each run, so later programs can use
+/// what earlier ones defined
+#[derive(Default)]
+pub struct Vm {
+    pub globals: Globals,
+    pub heap: Heap,
+    constructors: Constructors,
+    values: Vec<Option<Value>>,
+    /// The globals before the program being run, kept here to be collected
+    /// as roots until it finishes
+    saved: Option<Saved>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+struct Saved {
+    values: Vec<Option<Value>>,
+    constructors: Constructors,
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm::default()
+    }
+
+    pub fn compile(&mut self, tree: &Tree) -> Rc<Proto> {
+        Rc::new(super::compiler::compile(tree, &mut self.globals, &mut self.constructors))
+    }
+
+    /// Remembers the globals, to go back to if the next program compiled
+    /// doesn't run to the end
+    pub fn save(&mut self) {
+        self.saved = Some(Saved {
+            values: self.values.clone(),
+            constructors: self.constructors.clone(),
+        });
+    }
+
+    /// Goes back to the globals from before the program that failed
+    pub fn restore(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            self.values = saved.values;
+            self.constructors = saved.constructors;
+        }
+    }
+
+    /// Runs a function of no parameters, as made by [`Vm::compile`]
+    pub fn run(&mut self, proto: Rc<Proto>) -> Result<Value> {
+        if self.heap.should_collect() {
+            self.collect();
+        }
+        let closure = self.heap.alloc(Object::Closure(Closure {
+            proto: proto.clone(),
+            captures: Vec::new(),
+        }));
+        self.stack.push(Value::Closure(closure));
+        self.frames.push(Frame {
+            closure,
+            proto,
+            ip: 0,
+            base: self.stack.len(),
+        });
+        let result = self.finish();
+        if result.is_ok() {
+            self.saved = None;
+        }
+        result
+    }
+
+    /// The value of a global, unless it was never defined
+    pub fn global(&self, name: &'static Symbol) -> Option<Value> {
+        let index = self.globals.get(name)?;
+        self.values.get(index as usize).cloned().flatten()
+    }
+
+    /// Calls a function from outside any program; the caller has checked
+    /// that it takes `args`, whose data must already be on the heap
+    pub fn apply(&mut self, callee: Value, args: Vec<Value>) -> Result<Value> {
+        let argc = args.len();
+        self.stack.push(callee.clone());
+        let base = self.stack.len();
+        self.stack.extend(args);
+        if self.heap.should_collect() {
+            self.collect();
+        }
+
+        match callee {
+            Value::Closure(closure) => {
+                let proto = self.heap.closure(closure).proto.clone();
+                debug_assert_eq!(proto.arity, argc);
+                self.frames.push(Frame {
+                    closure,
+                    proto,
+                    ip: 0,
+                    base,
+                });
+                self.finish()
+            }
+            Value::Constructor { name, arity } => {
+                debug_assert_eq!(arity, argc);
+                let fields = self.stack.split_off(base);
+                self.pop();
+                let data = self.heap.alloc(Object::Data(Data { cons: name, fields }));
+                Ok(Value::Data(data))
+            }
+            other => unreachable!("calls are checked, but {} isn't a function", other.type_name()),
+        }
+    }
+
+    /// Runs the frame on top until it returns, leaving nothing behind if
+    /// that fails
+    fn finish(&mut self) -> Result<Value> {
+        let result = self.execute();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    /// Renders a value, with the data it refers to
+    pub fn show(&self, value: &Value) -> String {
+        self.heap.show(value)
+    }
+
+    /// Collects garbage; values the VM is working on must be on the stack
+    fn collect(&mut self) {
+        let roots = self.stack.iter().cloned();
+        let globals = self.values.iter().flatten().cloned();
+        let saved = self.saved.iter().flat_map(|saved| saved.values.iter().flatten().cloned());
+        let frames = self.frames.iter().map(|frame| Value::Closure(frame.closure));
+        self.heap.collect(roots.chain(globals).chain(saved).chain(frames));
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn execute(&mut self) -> Result<Value> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let chunk = &frame.proto.chunk;
+            let op = chunk.code[frame.ip];
+            let context = chunk.contexts[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Constant(i) => {
+                    let value = chunk.constants[i as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::Unit => self.stack.push(Value::Unit),
+                Op::True => self.stack.push(Value::Bool(true)),
+                Op::False => self.stack.push(Value::Bool(false)),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Slide(n) => {
+                    let top = self.pop();
+                    self.stack.truncate(self.stack.len() - n as usize);
+                    self.stack.push(top);
+                }
+                Op::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::GetCapture(i) => {
+                    let value = self.heap.closure(frame.closure).captures[i as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::Current => {
+                    let value = Value::Closure(frame.closure);
+                    self.stack.push(value);
+                }
+                Op::GetGlobal(i) => match self.values.get(i as usize) {
+                    Some(Some(value)) => self.stack.push(value.clone()),
+                    _ => {
+                        let name = self.globals.names[i as usize];
+                        return error(context, Undefined(name));
+                    }
+                },
+                Op::DefineGlobal(i) => {
+                    let value = self.pop();
+                    let i = i as usize;
+                    if self.values.len() <= i {
+                        self.values.resize(i + 1, None);
+                    }
+                    self.values[i] = Some(value);
+                }
+                Op::Field(i) => match self.pop() {
+                    Value::Data(data) => {
+                        let data = self.heap.data(data);
+                        match data.fields.get(i as usize) {
+                            Some(field) => {
+                                let field = field.clone();
+                                self.stack.push(field);
+                            }
+                            None => {
+                                let message = format!("`{}` has no field {}", data.cons.name, i);
+                                return error(context, Invalid(message));
+                            }
+                        }
+                    }
+                    other => return error(context, Invalid(format!("cannot take a field of {}", other.type_name()))),
+                },
+                Op::Test(i) => {
+                    let case = chunk.cases[i as usize].clone();
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(test(&case, &value, &self.heap)));
+                }
+                Op::NoMatch => {
+                    let value = self.pop();
+                    return error(context, NoMatch(self.show(&value)));
+                }
+                Op::Unparsed => return error(context, Invalid("this part of the program has a syntax error".to_string())),
+                Op::Closure(i) => {
+                    let proto = chunk.functions[i as usize].clone();
+                    if self.heap.should_collect() {
+                        self.collect();
+                    }
+
+                    let frame = self.frames.last().unwrap();
+                    let captures = proto
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.stack[frame.base + *slot as usize].clone(),
+                            Capture::Outer(i) => self.heap.closure(frame.closure).captures[*i as usize].clone(),
+                            Capture::Current => Value::Closure(frame.closure),
+                        })
+                        .collect();
+                    let closure = self.heap.alloc(Object::Closure(Closure { proto, captures }));
+                    self.stack.push(Value::Closure(closure));
+                }
+                Op::Call(argc) => self.call(argc as usize, false, context)?,
+                Op::TailCall(argc) => self.call(argc as usize, true, context)?,
+                Op::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.base - 1);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                Op::Jump(target) => frame.ip = target as usize,
+                Op::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.frames.last_mut().unwrap().ip = target as usize,
+                    other => return error(context, Invalid(format!("expected a Bool, found {}", other.type_name()))),
+                },
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = binary(op, &lhs, &rhs, &self.heap, context)?;
+                    self.stack.push(value);
+                }
+                Op::Unary(op) => {
+                    let value = match (op, self.pop()) {
+                        (UnOp::Neg, Value::Int(n)) => match n.checked_neg() {
+                            Some(n) => Value::Int(n),
+                            None => return error(context, Overflow),
+                        },
+                        (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                        (op, value) => {
+                            return error(context, Invalid(format!("cannot apply `{}` to {}", op, value.type_name())))
+                        }
+                    };
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    /// Calls the function under `argc` arguments on the stack; a tail call
+    /// replaces the current frame instead of adding one
+    fn call(&mut self, argc: usize, tail: bool, context: Context) -> Result<()> {
+        let callee = self.stack.len() - argc - 1;
+        match self.stack[callee] {
+            Value::Closure(closure) => {
+                let proto = self.heap.closure(closure).proto.clone();
+                if proto.arity != argc {
+                    let message = format!("function takes {} arguments but {} were given", proto.arity, argc);
+                    return error(context, Invalid(message));
+                }
+
+                if tail {
+                    let frame = self.frames.last_mut().unwrap();
+                    self.stack.drain(frame.base - 1..callee);
+                    frame.closure = closure;
+                    frame.proto = proto;
+                    frame.ip = 0;
+                    return Ok(());
+                }
+
+                if self.frames.len() >= MAX_FRAMES {
+                    return error(context, StackOverflow);
+                }
+                self.frames.push(Frame {
+                    closure,
+                    proto,
+                    ip: 0,
+                    base: callee + 1,
+                });
+                Ok(())
+            }
+            Value::Constructor { name, arity } => {
+                if arity != argc {
+                    let message = format!(
+                        "constructor `{}` takes {} fields but {} were given",
+                        name.name, arity, argc
+                    );
+                    return error(context, Invalid(message));
+                }
+                if self.heap.should_collect() {
+                    self.collect();
+                }
+                let fields = self.stack.split_off(callee + 1);
+                self.pop();
+                let data = self.heap.alloc(Object::Data(Data { cons: name, fields }));
+                self.stack.push(Value::Data(data));
+                Ok(())
+            }
+            ref other => error(context, Invalid(format!("cannot call {}", other.type_name()))),
+        }
+    }
+}
+
+fn error<T>(context: Context, kind: RuntimeErrorKind) -> Result<T> {
+    Err(RuntimeError { kind, context })
+}
+
+fn test(case: &Case, value: &Value, heap: &Heap) -> bool {
+    match (case, value) {
+        (Case::Cons { name, .. }, Value::Data(data)) => heap.data(*data).cons == *name,
+        (Case::Int(a), Value::Int(b)) => a == b,
+        (Case::Bool(a), Value::Bool(b)) => a == b,
+        (Case::Str(a), Value::Str(b)) => a == b,
+        (Case::Char(a), Value::Char(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn binary(op: BinOp, lhs: &Value, rhs: &Value, heap: &Heap, context: Context) -> Result<Value> {
+    use BinOp::*;
+
+    let value = match (op, lhs, rhs) {
+        (Eq | NotEq, _, _) => match heap.equals(lhs, rhs) {
+            Some(eq) => Value::Bool(eq == (op == Eq)),
+            None => return error(context, Incomparable),
+        },
+        (_, Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            let arith = match op {
+                Add => a.checked_add(b),
+                Sub => a.checked_sub(b),
+                Mul => a.checked_mul(b),
+                Div | Rem if b == 0 => return error(context, DivisionByZero),
+                Div => a.checked_div(b),
+                Rem => a.checked_rem(b),
+                Lt => return Ok(Value::Bool(a < b)),
+                Le => return Ok(Value::Bool(a <= b)),
+                Gt => return Ok(Value::Bool(a > b)),
+                Ge => return Ok(Value::Bool(a >= b)),
+                // compiled to jumps
+                Eq | NotEq | And | Or => unreachable!(),
+            };
+            match arith {
+                Some(n) => Value::Int(n),
+                None => return error(context, Overflow),
+            }
+        }
+        _ => {
+            let message = format!("cannot apply `{}` to {} and {}", op, lhs.type_name(), rhs.type_name());
+            return error(context, Invalid(message));
+        }
+    };
+
+    Ok(value)
+}
@@ -0,0 +1,10 @@
+//! Compiles the AST to bytecode and runs it on a stack machine
+
+pub mod bytecode;
+mod compiler;
+pub mod heap;
+mod machine;
+pub mod value;
+
+pub use machine::Vm;
+pub use value::Value;
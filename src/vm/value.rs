@@ -0,0 +1,113 @@
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{context::Context, diagnostic::Diagnostic, util::Symbol};
+
+use super::heap::Gc;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Str(Rc<str>),
+    Char(char),
+    /// A fully applied data constructor
+    Data(Gc),
+    /// A data constructor still waiting for its fields
+    Constructor { name: &'static Symbol, arity: usize },
+    Closure(Gc),
+}
+
+/// Values on the heap are only shown by kind; [`Heap::show`] shows them in full
+///
+/// [`Heap::show`]: super::heap::Heap::show
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Data(_) => write!(f, "<data>"),
+            Value::Constructor { name, .. } => write!(f, "<constructor {}>", name.name),
+            Value::Closure(_) => write!(f, "<fn>"),
+        }
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Int(_) => "Int",
+            Value::Bool(_) => "Bool",
+            Value::Str(_) => "String",
+            Value::Char(_) => "Char",
+            Value::Data(_) => "data value",
+            Value::Constructor { .. } | Value::Closure(_) => "function",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    Overflow,
+    StackOverflow,
+    /// No arm matched the value, shown here
+    NoMatch(String),
+    /// A global whose definition failed while running
+    Undefined(&'static Symbol),
+    Incomparable,
+    /// Something the type checker rules out, like calling an Int
+    Invalid(String),
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub context: Context,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use RuntimeErrorKind::*;
+        match &self.kind {
+            DivisionByZero => write!(f, "division by zero"),
+            Overflow => write!(f, "integer overflow"),
+            StackOverflow => write!(f, "stack overflow"),
+            NoMatch(value) => write!(f, "no match arm matches `{}`", value),
+            Undefined(name) => write!(f, "`{}` is not defined", name.name),
+            Incomparable => write!(f, "functions cannot be compared"),
+            Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        use RuntimeErrorKind::*;
+        let diag = Diagnostic::error(self.to_string());
+
+        match self.kind {
+            DivisionByZero => diag.with_code("E0300").with_label(self.context, "the divisor is 0"),
+            Overflow => diag
+                .with_code("E0301")
+                .with_label(self.context, "")
+                .with_note(format!("integers are from {} to {}", i64::MIN, i64::MAX)),
+            StackOverflow => diag
+                .with_code("E0302")
+                .with_label(self.context, "")
+                .with_note(format!("calls may only nest {} deep, unless they are tail calls", super::machine::MAX_FRAMES)),
+            NoMatch(_) => diag.with_code("E0303").with_label(self.context, ""),
+            Undefined(_) => diag
+                .with_code("E0304")
+                .with_label(self.context, "")
+                .with_note("its definition failed while running"),
+            Incomparable => diag.with_code("E0305").with_label(self.context, ""),
+            Invalid(_) => diag.with_code("E0306").with_label(self.context, ""),
+        }
+    }
+}
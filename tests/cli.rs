@@ -0,0 +1,155 @@
+mod common;
+
+use common::snd;
+use snd_language::json::Json;
+
+#[test]
+fn exit_codes() {
+    assert_eq!(snd(&["run", "-"], "1 + 2").status.code(), Some(0));
+    assert_eq!(snd(&["check", "-"], "1 + true").status.code(), Some(1));
+    assert_eq!(snd(&["run", "-"], "let x = ").status.code(), Some(1));
+    assert_eq!(snd(&["run", "-"], "1 / 0").status.code(), Some(2));
+    assert_eq!(snd(&["run", "--no-such-option"], "").status.code(), Some(1));
+}
+
+#[test]
+fn check_does_not_run() {
+    let output = snd(&["check", "-"], "1 / 0");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn emit_stops_early() {
+    let output = snd(&["check", "--emit=types", "-"], "let id = fn(x) = x");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "id : fn('a) -> 'a\n");
+
+    let output = snd(&["--emit=tokens", "-"], "1 / 0");
+    assert!(output.status.success());
+    assert_eq!(output.stdout.iter().filter(|&&b| b == b'\n').count(), 3);
+}
+
+#[test]
+fn complete_matches_have_no_default() {
+    let program = "data C = R | G | B\nlet f = fn(c) = match c\n| R => 1\n| G => 2\n| B => 3\nf(B)";
+    let output = snd(&["run", "--dump-bytecode", "-"], program);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // the last constructor is known without testing for it
+    assert_eq!(stdout.matches("Test").count(), 2, "{}", stdout);
+    assert!(!stdout.contains("NoMatch"), "{}", stdout);
+    assert_eq!(String::from_utf8_lossy(&snd(&["run", "-"], program).stdout), "3\n");
+}
+
+#[test]
+fn repl_forgets_definitions_that_fail() {
+    let output = snd(&["repl"], "let a = 1 / 0\na\nlet a = 2\na\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0201"), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a : Int\n2\n");
+}
+
+#[test]
+fn repl_keeps_values_of_definitions_that_fail() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("redefines.snd");
+    std::fs::write(&path, "let a = true\nlet b = 1 / 0").unwrap();
+    let input = format!("let a = 2\n:load {}\n:type a\na + 1\n", path.display());
+    let output = snd(&["repl"], &input);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0300") && !stderr.contains("E0306"), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a : Int\nInt\n3\n");
+}
+
+#[test]
+fn repl_runs_an_entry_left_open_at_the_end() {
+    let output = snd(&["repl"], "let f = fn(x) = match x\n    | 0 => 1\n    | _ => 2\nf(5)\n");
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "f : fn(Int) -> Int\n2\n");
+}
+
+#[test]
+fn repl_entries_shadow_earlier_types() {
+    let input = "data List = Cons(Int, List) | Nil\nlet f = fn(l) = match l\n    | Cons(x, _) => x\n    | Nil => 0\n\n:load examples/bubble.snd\n";
+    let output = snd(&["repl"], input);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("error"), "{}", stderr);
+    assert!(stdout.ends_with("Cons(5, Cons(4, Cons(3, Cons(2, Cons(1, Nil)))))\n"), "{}", stdout);
+
+    // `f` takes the first `List`, which the new `Cons` doesn't make
+    let input = format!("{}f(Cons(1, Nil))\n", input);
+    let stderr = String::from_utf8_lossy(&snd(&["repl"], &input).stderr).to_string();
+    assert!(stderr.contains("E0200") && stderr.contains("different types of the same name"), "{}", stderr);
+}
+
+#[test]
+fn bubble_example_runs_with_its_warning() {
+    // its `Cons(x, Nil) = ...` arm is kept as a fixture for W0100
+    let output = snd(&["examples/bubble.snd"], "");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Cons(5, Cons(4, Cons(3, Cons(2, Cons(1, Nil)))))\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("W0100").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn formatted_examples_run_the_same() {
+    for example in ["examples/bubble.snd", "examples/generic.snd"] {
+        let source = std::fs::read_to_string(example).unwrap();
+        let formatted = snd(&["fmt", "-"], &source);
+        assert!(formatted.status.success(), "{}", String::from_utf8_lossy(&formatted.stderr));
+
+        let formatted = String::from_utf8(formatted.stdout).unwrap();
+        assert_eq!(snd(&["-"], &formatted).stdout, snd(&["-"], &source).stdout, "{}", example);
+    }
+}
+
+#[test]
+fn later_color_options_win() {
+    let output = snd(&["check", "--color=always", "-"], "1 + true");
+    assert!(output.stderr.contains(&0x1b));
+    // stderr isn't a terminal here
+    let output = snd(&["check", "--color=always", "--color=auto", "-"], "1 + true");
+    assert!(!output.stderr.contains(&0x1b), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn json_errors() {
+    let output = snd(&["check", "--error-format=json", "-"], "let x = 1\nif true x else \"a\"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    let diag = Json::parse(stderr.trim_end()).unwrap();
+
+    let field = |json: &Json, key: &str| json.get(key).cloned().unwrap();
+    let span = |json: &Json| {
+        ["file", "byte_start", "byte_end", "line_start", "column_start", "line_end", "column_end"].map(|key| field(json, key))
+    };
+    // both labels are on line 2, which starts at byte 10
+    let expected_span = |start: usize, end: usize| {
+        let (line, column) = (2.0, (start - 10 + 1) as f64);
+        [
+            Json::String("<stdin>".to_string()),
+            Json::Number(start as f64),
+            Json::Number(end as f64),
+            Json::Number(line),
+            Json::Number(column),
+            Json::Number(line),
+            Json::Number(column + (end - start) as f64),
+        ]
+    };
+
+    assert_eq!(field(&diag, "severity"), Json::String("error".to_string()));
+    assert_eq!(field(&diag, "code"), Json::String("E0200".to_string()));
+    assert_eq!(field(&diag, "message"), Json::String("mismatched types".to_string()));
+    // the diagnostic's own span is its primary label's
+    assert_eq!(span(&diag), expected_span(25, 28));
+
+    let labels = field(&diag, "labels");
+    let labels = labels.as_array().unwrap();
+    assert_eq!(labels.len(), 2);
+    assert_eq!(span(&labels[0]), expected_span(25, 28));
+    assert_eq!(field(&labels[0], "message"), Json::String("expected Int, found String".to_string()));
+    assert_eq!(field(&labels[0], "primary"), Json::Bool(true));
+    assert_eq!(span(&labels[1]), expected_span(18, 19));
+    assert_eq!(field(&labels[1], "message"), Json::String("the `if` branch's type".to_string()));
+    assert_eq!(field(&labels[1], "primary"), Json::Bool(false));
+}
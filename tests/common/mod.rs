@@ -0,0 +1,17 @@
+//! Helpers shared by the integration tests
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Runs `snd` with `args`, piping it `program`, which it reads for a path of `-`
+pub fn snd(args: &[&str], program: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_snd"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(program.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
@@ -0,0 +1,144 @@
+use snd_language::cst::{self, Element, Node, NodeKind, TriviaKind};
+use snd_language::lexer::{self, Lexer};
+use snd_language::parser::Parser;
+use snd_language::source;
+
+/// The program's concrete syntax tree, and the file it was read into
+fn build(name: &str, src: &str) -> (Node, source::FileId) {
+    let lexer = Lexer::from_source(name, src);
+    let file = lexer.file();
+    let tokens = lexer.lex_with_trivia().unwrap_or_else(|_| panic!("{} doesn't lex", name));
+    let syntax = lexer::layout(tokens.iter().filter(|t| !t.is_trivia()).cloned().collect()).unwrap();
+    let tree = Parser::new(syntax).parse().unwrap_or_else(|_| panic!("{} doesn't parse:\n{}", name, src));
+    (cst::build(tokens, &tree), file)
+}
+
+fn round_trip(name: &str, src: &str) {
+    let (tree, file) = build(name, src);
+    assert_eq!(tree.to_string(), src, "{} changed:\n{}", name, tree.dump());
+    source::remove(file);
+}
+
+fn examples() -> Vec<(String, String)> {
+    let mut examples = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "snd"))
+        .map(|path| (path.display().to_string(), std::fs::read_to_string(&path).unwrap()))
+        .collect::<Vec<_>>();
+    examples.sort();
+    examples
+}
+
+/// Spreads trivia between the tokens of `src`, where it can't change what
+/// they mean: not in indentation, which is layout, and only at the ends of
+/// lines if it spans lines
+fn perturb(src: &str, seed: u64) -> String {
+    const INLINE: [&str; 4] = [" ", "\t", "  ", "/* x */"];
+    const LINE_END: [&str; 3] = ["/* a\n b */", " // note", "\n"];
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    let mut out = String::new();
+    let mut in_comment = false;
+    let mut in_literal = None;
+    let mut indentation = true;
+    for c in src.chars() {
+        let code = in_literal.is_none() && !in_comment;
+        if code && !indentation && matches!(c, ' ' | '(' | ')' | ',') && next() % 3 == 0 {
+            out += INLINE[next() % INLINE.len()];
+        }
+        if code && c == '\n' && next() % 3 == 0 {
+            out += LINE_END[next() % LINE_END.len()];
+        }
+        out.push(c);
+        indentation = c == '\n' || indentation && c.is_whitespace();
+
+        match in_literal {
+            Some(quote) if c == quote && !out[..out.len() - 1].ends_with('\\') => in_literal = None,
+            None if !in_comment && (c == '"' || c == '\'') => in_literal = Some(c),
+            _ => {}
+        }
+        if in_literal.is_none() {
+            if !in_comment && out.ends_with("//") {
+                in_comment = true;
+            } else if in_comment && c == '\n' {
+                in_comment = false;
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn examples_round_trip() {
+    for (name, src) in examples() {
+        round_trip(&name, &src);
+    }
+}
+
+#[test]
+fn perturbed_examples_round_trip() {
+    for (name, src) in examples() {
+        for seed in 0..20 {
+            round_trip(&format!("{} (seed {})", name, seed), &perturb(&src, seed));
+        }
+    }
+}
+
+#[test]
+fn odd_layouts_round_trip() {
+    for src in [
+        "",
+        "   \n\n",
+        "// only a comment",
+        "1",
+        "1\n",
+        "\r\nlet x = 1\r\nx\r\n",
+        "data T = A | B(Int)   \nlet f = fn(t) = match t\n\t| A => 0 /* a */\n\t| B(n) => n\nf(B(2))  // end",
+        "{ let s = \"a // not a comment\"\n  s }",
+    ] {
+        round_trip("<test>", src);
+    }
+}
+
+#[test]
+fn trivia_is_attached() {
+    let (tree, file) = build("<test>", "let x = 1 // one\n\n  /* two */ x");
+    let tokens = tree.tokens();
+
+    // a comment on the token's line trails it; the newline after doesn't
+    let one = tokens.iter().find(|t| t.context.start == 8).unwrap();
+    let kinds = one.trailing.iter().map(|t| t.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [TriviaKind::Whitespace, TriviaKind::Comment]);
+
+    let x = tokens.iter().find(|t| t.context.start == 30).unwrap();
+    let kinds = x.leading.iter().map(|t| t.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            TriviaKind::Newline,
+            TriviaKind::Newline,
+            TriviaKind::Whitespace,
+            TriviaKind::Comment,
+            TriviaKind::Whitespace
+        ]
+    );
+    for token in &tokens {
+        assert!(token.trailing.iter().all(|t| t.kind != TriviaKind::Newline));
+    }
+
+    let kinds = tree
+        .children
+        .iter()
+        .filter_map(|child| match child {
+            Element::Node(node) => Some(node.kind),
+            Element::Token(_) => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, [NodeKind::Let, NodeKind::Name]);
+    source::remove(file);
+}
@@ -0,0 +1,56 @@
+use snd_language::{eval, lexer::Lexer, parser::Parser, source};
+
+mod common;
+
+use common::snd;
+
+/// Runs `src` after a function `f` matching a list with `arms`, on the VM
+/// and in the evaluator, which must agree
+fn run(arms: &str, src: &str) -> String {
+    let program = format!("data List a = Cons(a, List a) | Nil\nlet f = fn(l) = match l\n{}\n{}\n", arms, src);
+    let output = snd(&["-"], &program);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let result = String::from_utf8(output.stdout).unwrap().trim().to_string();
+
+    assert_eq!(evaluate(&program), result, "{}", program);
+    result
+}
+
+fn evaluate(program: &str) -> String {
+    let lexer = Lexer::from_source("<test>", program);
+    let file = lexer.file();
+    let tree = Parser::new(lexer.lex().unwrap()).parse().unwrap();
+    let value = eval::eval(&tree, &eval::Env::new()).unwrap();
+    source::remove(file);
+    value.to_string()
+}
+
+#[test]
+fn nested_constructors() {
+    let arms = "| Cons(1, Nil) => 0\n| Cons(x, Cons(y, _)) => x + y\n| Cons(x, _) => x\n| Nil => 3";
+    for (list, result) in [
+        ("Cons(1, Nil)", "0"),
+        ("Cons(1, Cons(2, Nil))", "3"),
+        ("Cons(4, Cons(5, Cons(6, Nil)))", "9"),
+        ("Cons(7, Nil)", "7"),
+        ("Nil", "3"),
+    ] {
+        assert_eq!(run(arms, &format!("f({})", list)), result, "{}", list);
+    }
+}
+
+#[test]
+fn first_matching_arm_wins() {
+    let arms = "| Cons(x, _) => x\n| Cons(1, Nil) => 0\n| _ => 2";
+    assert_eq!(run(arms, "f(Cons(1, Nil))"), "1");
+    assert_eq!(run(arms, "f(Nil)"), "2");
+}
+
+#[test]
+fn examples_evaluate_as_they_run() {
+    for example in ["examples/bubble.snd", "examples/generic.snd"] {
+        let program = std::fs::read_to_string(example).unwrap();
+        let output = snd(&[example], "");
+        assert_eq!(evaluate(&program), String::from_utf8(output.stdout).unwrap().trim(), "{}", example);
+    }
+}
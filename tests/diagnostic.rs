@@ -0,0 +1,122 @@
+use snd_language::context::Context;
+use snd_language::diagnostic::Diagnostic;
+use snd_language::source::{self, FileId};
+
+/// The context of the first `text` in `file`
+fn at(file: FileId, text: &str) -> Context {
+    let start = source::get(file).src.find(text).unwrap();
+    Context { file, start, len: text.len() }
+}
+
+/// From the start of `from` to the end of `to`
+fn between(file: FileId, from: &str, to: &str) -> Context {
+    let (from, to) = (at(file, from), at(file, to));
+    Context { file, start: from.start, len: to.start + to.len - from.start }
+}
+
+/// `text` without its ANSI escape sequences
+fn strip_color(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => while chars.next().is_some_and(|c| c != 'm') {},
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn mismatch() -> Diagnostic {
+    let src = "let f = fn(x) = match x\n    | 0 => 1\n    | 1 => 2\n    | 2 => 3\n    | 3 => 4\n    | _ => 5\nf(true)";
+    let file = source::add("multi.snd", src);
+    Diagnostic::error("mismatched types")
+        .with_code("E0200")
+        .with_label(at(file, "true"), "expected Int, found Bool")
+        .with_secondary(between(file, "fn(x)", "_ => 5"), "this function takes an Int")
+        .with_note("arguments are checked against the parameters' types")
+        .with_help("pass an Int")
+}
+
+#[test]
+fn multi_line_spans() {
+    // the middle of a long span is left out, and its bar runs down the left
+    assert_eq!(
+        mismatch().render(false),
+        "\
+error[E0200]: mismatched types
+ --> multi.snd:7:3
+  |
+1 |   let f = fn(x) = match x
+  |  _________-
+2 | |     | 0 => 1
+...
+5 | |     | 3 => 4
+6 | |     | _ => 5
+  | |____________- this function takes an Int
+7 |   f(true)
+  |     ^^^^ expected Int, found Bool
+  |
+  = note: arguments are checked against the parameters' types
+  = help: pass an Int
+"
+    );
+}
+
+#[test]
+fn nested_multi_line_spans() {
+    let file = source::add("nested.snd", "let a = {\n  1 +\n  2\n}\na");
+    let diag = Diagnostic::error("nested")
+        .with_label(between(file, "{", "}"), "outer")
+        .with_secondary(between(file, "1", "2"), "inner");
+    assert_eq!(
+        diag.render(false),
+        "\
+error: nested
+ --> nested.snd:1:9
+  |
+1 |     let a = {
+  |  ___________^
+2 | |     1 +
+  | |  ___-
+3 | | |   2
+  | | |___- inner
+4 | |   }
+  | |___^ outer
+"
+    );
+}
+
+#[test]
+fn gutter_fits_the_widest_line_number() {
+    let src = (1..=12).map(|i| format!("let x{} = {}\n", i, i)).collect::<String>();
+    let file = source::add("gutter.snd", &src);
+    let diag = Diagnostic::warning("unused")
+        .with_label(at(file, "x9"), "first")
+        .with_secondary(at(file, "x10"), "second");
+    assert_eq!(
+        diag.render(false),
+        "\
+warning: unused
+  --> gutter.snd:9:5
+   |
+ 9 | let x9 = 9
+   |     ^^ first
+10 | let x10 = 10
+   |     --- second
+"
+    );
+}
+
+#[test]
+fn color() {
+    let diag = mismatch();
+    let colored = diag.render(true);
+    assert_eq!(strip_color(&colored), diag.render(false));
+
+    // the primary label in the severity's color, secondary ones in blue
+    assert!(colored.starts_with("\x1b[1;31merror[E0200]\x1b[0m"), "{:?}", colored);
+    assert!(colored.contains("\x1b[1;31m^^^^\x1b[0m\x1b[1;31m expected Int, found Bool\x1b[0m"), "{:?}", colored);
+    assert!(colored.contains("\x1b[1;34m-\x1b[0m\x1b[1;34m this function takes an Int\x1b[0m"), "{:?}", colored);
+    assert!(!diag.render(false).contains('\x1b'));
+}
@@ -0,0 +1,170 @@
+use snd_language::{Engine, ErrorKind, Value};
+
+fn list(items: impl IntoIterator<Item = i64>) -> Value {
+    let items = items.into_iter().collect::<Vec<_>>();
+    items
+        .into_iter()
+        .rev()
+        .fold(Value::data("Nil", []), |rest, x| Value::data("Cons", [x.into(), rest]))
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .load(
+            "lists.snd",
+            "
+            data List a = Cons(a, List a) | Nil
+            let sum = fn(list) = match list
+                | Nil => 0
+                | Cons(x, rest) => x + sum(rest)
+            let range = fn(from, to) = if from >= to Nil else Cons(from, range(from + 1, to))
+            let greet = fn(name) = name
+            let div = fn(a, b) = a / b
+            ",
+        )
+        .unwrap();
+    engine
+}
+
+#[test]
+fn calls_with_rust_values() {
+    let mut engine = engine();
+    assert_eq!(engine.call("sum", &[list([1, 2, 3])]).unwrap(), Value::Int(6));
+    assert_eq!(engine.call("range", &[0.into(), 3.into()]).unwrap(), list([0, 1, 2]));
+    assert_eq!(engine.call("greet", &["a \"quoted\"\nname".into()]).unwrap().as_str(), Some("a \"quoted\"\nname"));
+    assert_eq!(engine.call("div", &[i64::MIN.into(), 1.into()]).unwrap(), Value::Int(i64::MIN));
+}
+
+#[test]
+fn later_loads_see_earlier_definitions() {
+    let mut engine = engine();
+    engine.load("more.snd", "let total = fn(n) = sum(range(0, n))").unwrap();
+    assert_eq!(engine.call("total", &[100.into()]).unwrap(), Value::Int(4950));
+    assert_eq!(engine.load("expr.snd", "total(4)").unwrap(), Value::Int(6));
+}
+
+#[test]
+fn failed_loads_define_nothing() {
+    let mut engine = engine();
+    engine.load("a.snd", "let a = 2").unwrap();
+    let err = engine.load("b.snd", "let a = true\nlet b = 1 / 0").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Runtime);
+    assert_eq!(engine.load("c.snd", "a + 1").unwrap(), Value::Int(3));
+    let err = engine.load("d.snd", "b").unwrap_err();
+    assert!(err.to_string().contains("E0201"), "{}", err);
+}
+
+#[test]
+fn long_results() {
+    let mut engine = engine();
+    let result = engine.call("range", &[0.into(), 10000.into()]).unwrap();
+    let mut length = 0;
+    let mut rest = &result;
+    while let Value::Data { fields, .. } = rest {
+        match fields.as_slice() {
+            [_, tail] => rest = tail,
+            _ => break,
+        }
+        length += 1;
+    }
+    assert_eq!(length, 10000);
+}
+
+#[test]
+fn errors() {
+    let mut engine = engine();
+    assert_eq!(engine.call("sum", &[true.into()]).unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.call("nothing", &[]).unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.call("sum(Nil) + sum", &[list([])]).unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.call("sum", &[Value::data("Nil) + (", [])]).unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.call("div", &[1.into(), 0.into()]).unwrap_err().kind(), ErrorKind::Runtime);
+
+    let err = engine.call("sum", &[1.into()]).unwrap_err();
+    assert!(err.to_string().contains("E0200"), "{}", err);
+
+    // arguments are checked down to the fields of their data
+    let bools = Value::data("Cons", [true.into(), Value::data("Nil", [])]);
+    let err = engine.call("sum", &[bools]).unwrap_err();
+    assert!(err.to_string().contains("E0200"), "{}", err);
+    let err = engine.call("sum", &[Value::data("Cons", [1.into()])]).unwrap_err();
+    assert!(err.to_string().contains("E0204"), "{}", err);
+    assert_eq!(engine.call("greet", &[Value::Function]).unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.call("sum", &[]).unwrap_err().kind(), ErrorKind::Compile);
+}
+
+#[test]
+fn runtime_error_codes() {
+    let code = |src: &str| {
+        let err = Engine::new().load("runtime.snd", src).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Runtime);
+        err.diagnostics()[0].code
+    };
+    assert_eq!(code("1 / 0"), Some("E0300"));
+    assert_eq!(code("1 % 0"), Some("E0300"));
+    assert_eq!(code("9223372036854775807 + 1"), Some("E0301"));
+    assert_eq!(code("let f = fn(n) = 1 + f(n)\nf(0)"), Some("E0302"));
+    assert_eq!(code("match 3\n| 1 => 1"), Some("E0303"));
+}
+
+#[test]
+fn calls_constructors_and_generic_functions() {
+    let mut engine = engine();
+    assert_eq!(engine.call("Cons", &[1.into(), list([])]).unwrap(), list([1]));
+    engine.load("first.snd", "let first = fn(list, default) = match list\n    | Cons(x, _) => x\n    | Nil => default").unwrap();
+    assert_eq!(engine.call("first", &[list([]), 'x'.into()]).unwrap(), Value::Char('x'));
+    assert_eq!(engine.call("first", &[list([7]), 0.into()]).unwrap(), Value::Int(7));
+}
+
+#[test]
+fn warnings() {
+    let mut engine = engine();
+    engine.load("bool.snd", "let yes = fn(b) = match b\n    | true => 1").unwrap();
+    let warnings = engine.warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].to_string().contains("W0200"), "{}", warnings[0]);
+    assert!(engine.warnings().is_empty());
+}
+
+#[test]
+fn diagnostics_outlive_their_sources() {
+    let mut engine = engine();
+    let diagnostics = engine.load("bad.snd", "sum(1 + true)").unwrap_err().diagnostics().to_vec();
+    assert!(diagnostics[0].render(false).contains("sum(1 + true)"));
+
+    // the source of a program that defines nothing is removed right away
+    engine.load("warn.snd", "match true\n    | true => 1").unwrap();
+    let warnings = engine.warnings();
+    assert!(warnings[0].render(false).contains("match true"), "{}", warnings[0].render(false));
+}
+
+#[test]
+fn string_literals_are_not_symbols() {
+    let mut engine = Engine::new();
+    let value = engine.load("strings.snd", "match \"a literal\"\n| \"a pattern\" => 1\n| _ => 2").unwrap();
+    assert_eq!(value, Value::Int(2));
+    assert_eq!(snd_language::util::Symbol::get("a literal"), None);
+    assert_eq!(snd_language::util::Symbol::get("a pattern"), None);
+}
+
+#[test]
+fn types_of_definitions_and_expressions() {
+    let mut engine = engine();
+    let loaded = engine.load_typed("more.snd", "let total = fn(n) = sum(range(0, n))\nlet id = fn(x) = x\nrange(0, 2)").unwrap();
+    let definitions = [("total", "fn(Int) -> Int"), ("id", "fn('a) -> 'a")].map(|(n, t)| (n.to_string(), t.to_string()));
+    assert_eq!(loaded.definitions, definitions);
+    assert_eq!(loaded.value.to_string(), "Cons(0, Cons(1, Nil))");
+
+    assert_eq!(engine.type_of("expr.snd", "total").unwrap(), "fn(Int) -> Int");
+    assert_eq!(engine.type_of("expr.snd", "Cons(\"a\", Nil)").unwrap(), "List String");
+    assert_eq!(engine.type_of("expr.snd", "total(true)").unwrap_err().kind(), ErrorKind::Compile);
+}
+
+#[test]
+fn checking_runs_nothing_and_keeps_nothing() {
+    let mut engine = engine();
+    engine.check("check.snd", "let checked = 1 / 0\nmatch checked\n    | 1 => true").unwrap();
+    assert_eq!(engine.warnings().len(), 1);
+    assert_eq!(engine.check("check.snd", "sum(true)").unwrap_err().kind(), ErrorKind::Compile);
+    assert_eq!(engine.load("use.snd", "checked").unwrap_err().kind(), ErrorKind::Compile);
+}
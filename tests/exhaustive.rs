@@ -0,0 +1,78 @@
+use snd_language::{exhaustive, lexer::Lexer, parser::Parser, source};
+
+mod common;
+
+use common::snd;
+
+/// The string value of the first `field` in a line of JSON
+fn field<'a>(json: &'a str, field: &str) -> &'a str {
+    let start = json.find(&format!("\"{}\":\"", field)).unwrap() + field.len() + 4;
+    &json[start..start + json[start..].find('"').unwrap()]
+}
+
+/// The code and primary label of each warning about `match x` with `arms`
+fn warnings(arms: &str) -> Vec<(String, String)> {
+    let src = format!("data List a = Cons(a, List a) | Nil\nlet f = fn(x) = match x\n{}\n", arms);
+    let output = snd(&["--emit=types", "--error-format=json", "-"], &src);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+
+    stderr
+        .lines()
+        .map(|line| {
+            let labels = &line[line.find("\"labels\":").unwrap()..];
+            (field(line, "code").to_string(), field(labels, "message").to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn missing_pattern_witness() {
+    let missing = |arms: &str| {
+        let warnings = warnings(arms);
+        assert!(warnings.iter().all(|(code, _)| code == "W0200"), "{:?}", warnings);
+        warnings.into_iter().map(|(_, label)| label).collect::<Vec<_>>()
+    };
+    assert_eq!(missing("| Nil => 0"), ["pattern `Cons(_, _)` not covered"]);
+    assert_eq!(missing("| Nil => 0\n| Cons(x, Nil) => x"), ["pattern `Cons(_, Cons(_, _))` not covered"]);
+    assert_eq!(missing("| true => 0"), ["pattern `false` not covered"]);
+    assert_eq!(missing("| 0 => 0\n| 1 => 1"), ["pattern `_` not covered"]);
+    assert_eq!(missing("| Nil => 0\n| Cons(_, _) => 1"), Vec::<String>::new());
+}
+
+#[test]
+fn unreachable_arms() {
+    let unreachable = ("W0201".to_string(), "earlier arms already match every value here".to_string());
+    assert_eq!(
+        warnings("| Cons(x, rest) => 1\n| Nil => 2\n| Cons(1, Nil) => 3\n| _ => 4"),
+        [unreachable.clone(), unreachable]
+    );
+    assert_eq!(warnings("| true => 0\n| false => 1\n| true => 2").len(), 1);
+    assert_eq!(warnings("| 1 => 0\n| 2 => 1\n| 1 => 2\n| _ => 3").len(), 1);
+}
+
+#[test]
+fn unknown_constructors_cover_nothing() {
+    // checked programs only use declared constructors, but nothing else
+    // stops a tree from naming others
+    let warnings = |src: &str| {
+        let lexer = Lexer::from_source("<test>", src);
+        let file = lexer.file();
+        let tree = Parser::new(lexer.lex().unwrap()).parse().unwrap();
+        let warnings: Vec<_> = exhaustive::check(&tree)
+            .into_iter()
+            .map(|diag| (diag.code.unwrap(), diag.labels[0].message.clone()))
+            .collect();
+        source::remove(file);
+        warnings
+    };
+    assert_eq!(
+        warnings("match x\n| Foo => 1\n| Bar(y) => 2"),
+        [("W0200", "pattern `_` not covered".to_string())]
+    );
+    assert_eq!(warnings("match x\n| Foo(Bar) => 1\n| _ => 2"), []);
+    assert_eq!(
+        warnings("match x\n| Foo(Bar) => 1\n| Foo(_) => 2"),
+        [("W0200", "pattern `_` not covered".to_string())]
+    );
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::snd;
+
+fn fmt(program: &str) -> String {
+    let output = snd(&["fmt", "-"], program);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Every program in examples/
+fn examples() -> Vec<String> {
+    let mut paths = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_string())
+        .filter(|path| path.ends_with(".snd"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Programs laid out every which way, with comments in awkward places
+const MESSY: [&str; 8] = [
+    "data List a = Cons(a,List a)|Nil   // lists\nlet xs=Cons(1,Cons(2,Nil))\n\n\n\nxs",
+    "// leading\n\n/* block\n   comment */\nlet f = fn(x) = match x // by cases\n | 0 => 1 // zero\n // others\n | n => n * f(n - 1)\nf(5) // done\n// trailing",
+    "let main = {\n  // first\n  let a = 1 /* inline */ + 2\n  a // result\n  // last\n}\nmain",
+    "let sign = fn(n) = if n > 0 1 else if n < 0 { 0 - 1 } else match n\n | 0 => 0\n | _ => 1\nsign(0 - 3)",
+    "let pick = fn(b) = match b\n | true => (match b\n   | true => 1\n   | false => 2) // nested\n | false => 3\npick(true)",
+    "let x = -(1 + 2) * 3 - (4 - 5)\nlet y = x == 1 || !(x < 2) && true\n-x\n(y)",
+    "let a = (-(if true 1 else 2)) + 3\na",
+    "let n = - -1 -- negated twice\nlet m = 1 --2\nn + m",
+];
+
+#[test]
+fn formatting_is_idempotent() {
+    let examples = examples().iter().map(|path| std::fs::read_to_string(path).unwrap()).collect::<Vec<_>>();
+    for program in examples.iter().map(String::as_str).chain(MESSY) {
+        let once = fmt(program);
+        assert_eq!(fmt(&once), once, "formatting twice changed:\n{}", once);
+    }
+}
+
+#[test]
+fn formatting_keeps_meaning() {
+    for program in MESSY {
+        let formatted = fmt(program);
+        assert_eq!(snd(&["-"], &formatted).stdout, snd(&["-"], program).stdout, "{}", formatted);
+    }
+}
+
+#[test]
+fn comments_are_kept() {
+    for program in MESSY {
+        let formatted = fmt(program);
+        for comment in program.split("//").skip(1).map(|rest| rest.lines().next().unwrap().trim()) {
+            assert!(formatted.contains(comment), "lost `{}`:\n{}", comment, formatted);
+        }
+        for comment in program.split("--").skip(1).map(|rest| rest.lines().next().unwrap().trim()) {
+            assert!(formatted.contains(comment), "lost `{}`:\n{}", comment, formatted);
+        }
+        for comment in program.split("/*").skip(1).map(|rest| rest.split("*/").next().unwrap()) {
+            assert!(formatted.contains(comment), "lost `{}`:\n{}", comment, formatted);
+        }
+    }
+}
+
+#[test]
+fn canonical_layout() {
+    let formatted = fmt("let sort = fn(list) = match sorted(list)\n    | true  => list | false = sort(bubble(list))");
+    assert_eq!(
+        formatted,
+        "let sort = fn(list) = match sorted(list)\n    | true => list\n    | false => sort(bubble(list))\n"
+    );
+
+    let formatted = fmt("// about x\nlet x = 1 // one\n\n\nlet y = { x }");
+    assert_eq!(formatted, "// about x\nlet x = 1 // one\n\nlet y = {\n    x\n}\n");
+
+    // an `if` on one line stays there, one over several lines keeps its branches on their own
+    assert_eq!(fmt("let a = if true   1 else 2"), "let a = if true 1 else 2\n");
+    let formatted = fmt("let max = fn(a, b) = if a > b\n  a\n  else b");
+    assert_eq!(formatted, "let max = fn(a, b) = if a > b\n    a\nelse\n    b\n");
+}
+
+#[test]
+fn trailing_comments_stay_on_their_line() {
+    let program = "let x = 1 - -2 /* c */ // tail\nx\n";
+    assert_eq!(fmt(program), program);
+
+    let program = "let r = match 1\n    | 1 => 2 // one\n    | _ => 3 // last\nr\n";
+    assert_eq!(fmt(program), program);
+
+    let program = "let n = -(1 + 2) // parenthesized\nlet m = f((n)) // nested\nn\n";
+    assert_eq!(fmt(program), "let n = -(1 + 2) // parenthesized\nlet m = f(n) // nested\nn\n");
+
+    // what follows the last arm moves past its line comment
+    let formatted = fmt("let r = (match 1 | 1 => 2 | _ => 3 // last\n) + 1\nr");
+    assert_eq!(formatted, "let r = (match 1\n    | 1 => 2\n    | _ => 3 // last\n) + 1\nr\n");
+    assert_eq!(snd(&["-"], &formatted).stdout, b"3\n");
+    let formatted = fmt("let r = (match 1 | 1 => 2 | _ => 3 -- last\n) + 1\nr");
+    assert_eq!(formatted, "let r = (match 1\n    | 1 => 2\n    | _ => 3 -- last\n) + 1\nr\n");
+}
+
+#[test]
+fn scrutinee_matches_keep_their_arms() {
+    let formatted = fmt("let r = match (match 1 | 1 => 2 | _ => 3) | 2 => 10 | _ => 20\nr");
+    assert_eq!(
+        formatted,
+        "let r = match (match 1\n    | 1 => 2\n    | _ => 3)\n    | 2 => 10\n    | _ => 20\nr\n"
+    );
+    assert_eq!(snd(&["-"], &formatted).stdout, b"10\n");
+}
+
+#[test]
+fn check_mode() {
+    // bubble.snd keeps the layout it was written in, which snd fmt changes
+    for path in examples() {
+        let output = snd(&["fmt", "--check", &path], "");
+        let formatted = !path.ends_with("bubble.snd");
+        assert_eq!(output.status.success(), formatted, "{}: {}", path, String::from_utf8_lossy(&output.stderr));
+    }
+
+    let output = snd(&["fmt", "--check", "-"], "let x = 1\n");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = snd(&["fmt", "--check", "-"], "let  x = 1\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("<stdin> is not formatted"));
+
+    // the first line that changes is reported like any other error
+    let output = snd(&["fmt", "--check", "--error-format=json", "-"], "let x = 1\nlet  y = 2\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with('{'), "{}", stderr);
+    assert!(stderr.contains("\"line_start\":2"), "{}", stderr);
+    assert!(stderr.contains("formatted, this line is `let y = 2`"), "{}", stderr);
+}
@@ -0,0 +1,19 @@
+mod common;
+
+fn snd(args: &[&str]) -> (String, String) {
+    let output = common::snd(args, "");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (stdout, stderr)
+}
+
+#[test]
+fn stress_mode_gives_the_same_results() {
+    for example in ["examples/bubble.snd", "examples/generic.snd"] {
+        let (normal, _) = snd(&[example]);
+        let (stressed, stats) = snd(&["--gc-stress", "--gc-stats", example]);
+        assert_eq!(normal, stressed, "{}", example);
+        assert!(stats.lines().any(|line| line.starts_with("gc: ")), "{}", stats);
+    }
+}
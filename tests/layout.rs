@@ -0,0 +1,83 @@
+use snd_language::lexer::{LexError, Lexer, TokenKind};
+use snd_language::parser::{ParseError, Parser};
+use snd_language::source;
+
+/// The program's syntax tree, or the codes of its errors
+fn parse(src: &str) -> Result<String, Vec<&'static str>> {
+    let lexer = Lexer::from_source("<test>", src);
+    let file = lexer.file();
+    let result = match lexer.lex() {
+        Ok(tokens) => Parser::new(tokens)
+            .parse()
+            .map(|tree| tree.to_string())
+            .map_err(|errors| errors.iter().map(|err| ParseError::diagnostic(err).code.unwrap()).collect()),
+        Err(errors) => Err(errors.iter().map(|err| LexError::diagnostic(err).code.unwrap()).collect()),
+    };
+    source::remove(file);
+    result
+}
+
+fn layout(src: &str) -> Vec<TokenKind> {
+    let lexer = Lexer::from_source("<test>", src);
+    let file = lexer.file();
+    let tokens = lexer.lex().unwrap();
+    source::remove(file);
+    tokens.into_iter().filter(|t| t.is_layout()).map(|t| t.token).collect()
+}
+
+#[test]
+fn nested_arms_go_by_indentation() {
+    let src = "match a\n    | 1 => match b\n        | true => 1\n        | false => 2\n    | _ => 3\n";
+    assert_eq!(parse(src).unwrap(), "(block (match a (1 (match b (true 1) (false 2))) (_ 3)))");
+
+    // without lines to go by, arms still go to the innermost `match`
+    let src = "match a | 1 => match b | true => 1 | false => 2";
+    assert_eq!(parse(src).unwrap(), "(block (match a (1 (match b (true 1) (false 2)))))");
+}
+
+#[test]
+fn layout_tokens() {
+    use TokenKind::*;
+    let src = "let f = fn(x) = match x\n    | 1 => match x\n        | 1 => 2\n    | _ => 3\nf(1)";
+    assert_eq!(layout(src), [Indent, Indent, Dedent, Newline, Dedent]);
+
+    // brackets close the blocks opened in them
+    let src = "(match x\n    | 1 => 2\n    | _ => 3) + 1";
+    assert_eq!(layout(src), [Indent, Newline, Dedent]);
+
+    // a line at the arms' column that isn't an arm ends them
+    let src = "{\n    match x\n    | 1 => 2\n    x\n}";
+    assert_eq!(layout(src), [Indent, Dedent]);
+}
+
+#[test]
+fn lines_end_arms() {
+    let src = "let f = fn(x) = match x\n    | 1 => 2\n    | _ => 3\nf\n  (1)";
+    assert_eq!(parse(src).unwrap(), "(block (let f (fn (x) (match x (1 2) (_ 3)))) (f 1))");
+
+    let src = "data Shape = Circle(Int)\n    | Square(Int)\nlet x = 1";
+    assert_eq!(parse(src).unwrap(), "(block (data Shape (Circle Int) (Square Int)) (let x 1))");
+
+    let src = "let f = fn(x, y) = match x\n    | 1 => if y\n            2\n        else\n            3\n    | _ => 4";
+    assert_eq!(parse(src).unwrap(), "(block (let f (fn (x y) (match x (1 (if y 2 3)) (_ 4)))))");
+
+    // so does what follows the last arm on its line
+    let src = "let f = fn(x) = if x == 1 match x\n    | 1 => 10\n    | _ => 20 else 30\nf(1)";
+    assert_eq!(parse(src).unwrap(), "(block (let f (fn (x) (if (== x 1) (match x (1 10) (_ 20)) 30))) (f 1))");
+
+    // many such blocks, each with a dedent the parser skips
+    let src = "let f = fn(x) = if x == 1 match x\n    | 1 => 10\n    | _ => 20 else 30\n".repeat(5000);
+    assert_eq!(parse(&src).unwrap().matches("(match x (1 10) (_ 20))").count(), 5000);
+}
+
+#[test]
+fn inconsistent_indentation() {
+    // between the columns of two blocks of arms
+    assert_eq!(parse("match a\n    | 1 => match b\n        | 2 => 3\n      | 4 => 5"), Err(vec!["E0011"]));
+
+    // an inner `match` whose arms line up with the outer one's
+    assert_eq!(parse("match a\n    | 1 => match b\n    | 2 => 3"), Err(vec!["E0104"]));
+
+    // the rest of an arm left of its `|`
+    assert_eq!(parse("match a\n    | 1 => if b 2\n    else 3"), Err(vec!["E0103"]));
+}
@@ -0,0 +1,136 @@
+use std::process::Output;
+
+use snd_language::json::Json;
+
+mod common;
+
+fn ast(src: &str) -> Output {
+    common::snd(&["--emit=ast", "-"], src)
+}
+
+/// The tree printed for a program of one literal
+fn literal(src: &str) -> String {
+    let output = ast(src);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().trim_end().to_string()
+}
+
+/// The tokens of a program, without their locations
+fn tokens(src: &str) -> Vec<String> {
+    let output = common::snd(&["lex", "-"], src);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout.lines().map(|line| line.split_once(' ').unwrap().1.to_string()).collect()
+}
+
+/// Each error's code and the line and column it points at
+fn errors(src: &str) -> Vec<(String, String)> {
+    let output = ast(src);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines = stderr.lines().collect::<Vec<_>>();
+    lines
+        .windows(2)
+        .filter_map(|pair| {
+            let code = pair[0].strip_prefix("error[")?.split(']').next()?;
+            let (_, at) = pair[1].split_once("<stdin>:")?;
+            Some((code.to_string(), at.to_string()))
+        })
+        .collect()
+}
+
+/// Each diagnostic printed by `snd check` on `path`, parsed from JSON
+fn diagnostics(path: &str, src: &str) -> Vec<Json> {
+    let output = common::snd(&["check", "--error-format=json", path], src);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    stderr.lines().map(|line| Json::parse(line).unwrap()).collect()
+}
+
+/// The code of a diagnostic, and the bytes its span covers
+fn span(diag: &Json) -> (&str, Option<usize>, Option<usize>) {
+    let code = diag.get("code").and_then(Json::as_str).unwrap();
+    let offset = |key| diag.get(key).and_then(Json::as_usize);
+    (code, offset("byte_start"), offset("byte_end"))
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(literal(r#""\n\t\r\0\\\"\'""#), format!("(block {:?})", "\n\t\r\0\\\"'"));
+    assert_eq!(literal(r#""\u{48}\u{e9}\u{1F600}""#), "(block \"Hé😀\")");
+    assert_eq!(literal(r"'\''"), r"(block '\'')");
+    assert_eq!(literal(r"'\u{10FFFF}'"), format!("(block {:?})", '\u{10FFFF}'));
+}
+
+#[test]
+fn escape_errors() {
+    for (src, expected) in [
+        (r#""\q""#, vec![("E0006", "1:3")]),
+        (r#""\é""#, vec![("E0006", "1:3")]),
+        (r"'\q'", vec![("E0006", "1:3")]),
+        (r#""\u48""#, vec![("E0007", "1:4")]),
+        (r#""\u""#, vec![("E0007", "1:4")]),
+        (r#""\u{}""#, vec![("E0007", "1:2")]),
+        (r#""\u{g}""#, vec![("E0007", "1:5")]),
+        (r#""\u{1234567}""#, vec![("E0007", "1:11")]),
+        (r#""\u{110000}""#, vec![("E0007", "1:2")]),
+        (r#""\u{D800}""#, vec![("E0007", "1:2")]),
+        (r#""\u{48""#, vec![("E0007", "1:7")]),
+    ] {
+        let expected = expected.iter().map(|(code, at)| (code.to_string(), at.to_string())).collect::<Vec<_>>();
+        assert_eq!(errors(src), expected, "{}", src);
+    }
+}
+
+#[test]
+fn unterminated_escapes() {
+    let codes = |src| errors(src).into_iter().map(|(code, _)| code).collect::<Vec<_>>();
+    assert_eq!(codes(r#""\"#), ["E0002"]);
+    assert_eq!(codes(r#""\u{48"#), ["E0007", "E0002"]);
+
+    // the newline still ends the character literal
+    assert_eq!(codes("'\\\nx"), ["E0006", "E0003"]);
+    assert_eq!(errors("'\\\nx")[1].1, "1:1");
+}
+
+#[test]
+fn error_locations() {
+    // resolved through the file the error's context points into, counting
+    // columns in chars rather than bytes
+    let errors = errors("let s = \"a\"\nlet t = \"é\\q\"");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].1, "2:12");
+}
+
+#[test]
+fn comments() {
+    assert_eq!(tokens("1 // one\n2 -- two\n3 /* three */"), ["integer `1`", "integer `2`", "integer `3`"]);
+    assert_eq!(tokens("/* a /* nested */ comment */ 1"), ["integer `1`"]);
+    assert_eq!(tokens("1 --2"), ["integer `1`"]);
+    assert_eq!(tokens("1 - -2"), ["integer `1`", "`-`", "`-`", "integer `2`"]);
+
+    // pointing at the `/*` left open
+    assert_eq!(errors("x /* a /* b */\n1"), [("E0001".to_string(), "1:3".to_string())]);
+}
+
+#[test]
+fn every_lexical_error_is_reported() {
+    let diagnostics = diagnostics("-", "let a = 1 $ 2\nlet b = 99999999999999999999\nlet c = 1 # 2");
+    let spans = diagnostics.iter().map(span).collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        [("E0008", Some(10), Some(11)), ("E0009", Some(22), Some(42)), ("E0008", Some(53), Some(54))]
+    );
+    assert_eq!(diagnostics[1].get("line_start").and_then(Json::as_usize), Some(2));
+    assert_eq!(diagnostics[1].get("column_start").and_then(Json::as_usize), Some(9));
+}
+
+#[test]
+fn unreadable_files() {
+    let diagnostics = diagnostics("no/such/file.snd", "");
+    assert_eq!(diagnostics.len(), 1);
+    // there's no source for a span to point into
+    assert_eq!(span(&diagnostics[0]), ("E0010", None, None));
+    let message = diagnostics[0].get("message").and_then(Json::as_str).unwrap();
+    assert!(message.starts_with("could not read no/such/file.snd: "), "{}", message);
+}
@@ -0,0 +1,250 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use snd_language::json::Json;
+
+const SHAPES: &str = "data Shape = Circle(Int) | Square(Int)
+let area = fn(shape) = match shape
+    | Circle(r) => 3 * r * r
+    | Square(s) => s * s
+let total = area(Circle(2)) + area(Square(3))
+let emoji = \"😀😀\" let copy = emoji
+";
+
+fn message(value: Json) -> String {
+    let body = value.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn request(id: usize, method: &str, params: Json) -> String {
+    message(Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+fn notification(method: &str, params: Json) -> String {
+    message(Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+fn document(uri: &str) -> Json {
+    Json::object([("textDocument", Json::object([("uri", uri.into())]))])
+}
+
+fn at(uri: &str, line: usize, character: usize) -> Json {
+    Json::object([
+        ("textDocument", Json::object([("uri", uri.into())])),
+        ("position", Json::object([("line", line.into()), ("character", character.into())])),
+    ])
+}
+
+fn open(uri: &str, text: &str) -> String {
+    let document = Json::object([
+        ("uri", uri.into()),
+        ("languageId", "snd".into()),
+        ("version", Json::Number(1.0)),
+        ("text", text.into()),
+    ]);
+    notification("textDocument/didOpen", Json::object([("textDocument", document)]))
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Json {
+    let position = |(line, character): (usize, usize)| {
+        Json::object([("line", line.into()), ("character", character.into())])
+    };
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+/// Runs the server over a transcript, returning what it sent back
+fn serve(transcript: &[String]) -> Vec<Json> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_snd"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(transcript.concat().as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut out = String::from_utf8(output.stdout).unwrap();
+    let mut messages = Vec::new();
+    while !out.is_empty() {
+        let (header, rest) = out.split_once("\r\n\r\n").unwrap();
+        let length = header.strip_prefix("Content-Length: ").unwrap().parse::<usize>().unwrap();
+        messages.push(Json::parse(&rest[..length]).unwrap());
+        out = rest[length..].to_string();
+    }
+    messages
+}
+
+fn reply(messages: &[Json], id: usize) -> &Json {
+    let message = messages
+        .iter()
+        .find(|message| message.get("id") == Some(&id.into()))
+        .unwrap();
+    message.get("result").unwrap()
+}
+
+/// The diagnostics of each `publishDiagnostics`, in order
+fn diagnostics(messages: &[Json]) -> Vec<&[Json]> {
+    messages
+        .iter()
+        .filter(|message| message.get("method") == Some(&"textDocument/publishDiagnostics".into()))
+        .map(|message| message.get("params").unwrap().get("diagnostics").unwrap().as_array().unwrap())
+        .collect()
+}
+
+fn hover_text(result: &Json) -> &str {
+    result.get("contents").unwrap().get("value").unwrap().as_str().unwrap()
+}
+
+#[test]
+fn navigation() {
+    let uri = "file:///shapes.snd";
+    let messages = serve(&[
+        request(1, "initialize", Json::object([("capabilities", Json::object::<&str>([]))])),
+        notification("initialized", Json::object::<&str>([])),
+        open(uri, SHAPES),
+        request(2, "textDocument/hover", at(uri, 4, 13)),
+        request(3, "textDocument/definition", at(uri, 4, 13)),
+        request(4, "textDocument/hover", at(uri, 2, 23)),
+        request(5, "textDocument/definition", at(uri, 2, 23)),
+        request(6, "textDocument/definition", at(uri, 4, 19)),
+        request(7, "textDocument/definition", at(uri, 5, 31)),
+        request(8, "textDocument/hover", at(uri, 5, 31)),
+        request(9, "textDocument/documentSymbol", document(uri)),
+        request(10, "textDocument/hover", at(uri, 0, 0)),
+        request(11, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+
+    let capabilities = reply(&messages, 1).get("capabilities").unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&true.into()));
+    assert_eq!(diagnostics(&messages), [&[] as &[Json]]);
+
+    let hover = reply(&messages, 2);
+    assert_eq!(hover_text(hover), "```snd\narea : fn(Shape) -> Int\n```");
+    assert_eq!(hover.get("range"), Some(&range((4, 12), (4, 16))));
+
+    let location = reply(&messages, 3);
+    assert_eq!(location.get("uri"), Some(&uri.into()));
+    assert_eq!(location.get("range"), Some(&range((1, 4), (1, 8))));
+
+    assert_eq!(hover_text(reply(&messages, 4)), "```snd\nr : Int\n```");
+    assert_eq!(reply(&messages, 5).get("range"), Some(&range((2, 13), (2, 14))));
+    assert_eq!(reply(&messages, 6).get("range"), Some(&range((0, 13), (0, 19))));
+
+    // columns count UTF-16 code units, two for each emoji
+    assert_eq!(reply(&messages, 7).get("range"), Some(&range((5, 4), (5, 9))));
+    assert_eq!(reply(&messages, 8).get("range"), Some(&range((5, 30), (5, 35))));
+
+    let symbols = reply(&messages, 9).as_array().unwrap();
+    let names = symbols
+        .iter()
+        .map(|symbol| symbol.get("name").unwrap().as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Shape", "area", "total", "emoji", "copy"]);
+    let constructors = symbols[0].get("children").unwrap().as_array().unwrap();
+    assert_eq!(constructors.len(), 2);
+    assert_eq!(symbols[1].get("detail"), Some(&"fn(Shape) -> Int".into()));
+
+    assert_eq!(reply(&messages, 10), &Json::Null);
+    assert_eq!(reply(&messages, 11), &Json::Null);
+}
+
+#[test]
+fn diagnostics_follow_edits() {
+    let uri = "file:///broken.snd";
+    let change = |text: &str| {
+        let changes = Json::Array(vec![Json::object([("text", text.into())])]);
+        let mut params = document(uri);
+        if let Json::Object(fields) = &mut params {
+            fields.push(("contentChanges".to_string(), changes));
+        }
+        notification("textDocument/didChange", params)
+    };
+    let save = |text: &str| {
+        let mut params = document(uri);
+        if let Json::Object(fields) = &mut params {
+            fields.push(("text".to_string(), text.into()));
+        }
+        notification("textDocument/didSave", params)
+    };
+
+    let messages = serve(&[
+        request(1, "initialize", Json::object([("capabilities", Json::object::<&str>([]))])),
+        open(uri, "let x = 1 + true\n"),
+        change("let x = 1 + 2\n"),
+        save("let x = match true\n    | true => 1\n"),
+        change("let x = \"unterminated\n"),
+        notification("textDocument/didClose", document(uri)),
+        request(2, "textDocument/hover", at(uri, 0, 4)),
+        request(3, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+
+    let published = diagnostics(&messages);
+    assert_eq!(published.len(), 5);
+
+    let error = &published[0][0];
+    assert_eq!(error.get("severity"), Some(&Json::Number(1.0)));
+    assert_eq!(error.get("code"), Some(&"E0200".into()));
+    assert_eq!(error.get("range"), Some(&range((0, 12), (0, 16))));
+
+    assert!(published[1].is_empty());
+
+    let warning = &published[2][0];
+    assert_eq!(warning.get("severity"), Some(&Json::Number(2.0)));
+    assert_eq!(warning.get("code"), Some(&"W0200".into()));
+
+    assert_eq!(published[3].len(), 1);
+    assert!(published[4].is_empty());
+
+    // closed documents are forgotten
+    let hover = messages.iter().find(|message| message.get("id") == Some(&Json::Number(2.0))).unwrap();
+    assert!(hover.get("error").is_some());
+}
+
+#[test]
+fn syntax_errors_keep_navigation() {
+    let uri = "file:///partial.snd";
+    let messages = serve(&[
+        request(1, "initialize", Json::object([("capabilities", Json::object::<&str>([]))])),
+        open(uri, "let a = 1\nlet b = (\nlet c = a + 1\nlet d = )\n"),
+        request(2, "textDocument/definition", at(uri, 2, 8)),
+        request(3, "textDocument/documentSymbol", document(uri)),
+        request(4, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+
+    let published = diagnostics(&messages);
+    let ranges = published[0].iter().map(|diag| diag.get("range").unwrap()).collect::<Vec<_>>();
+    assert_eq!(ranges, [&range((2, 0), (2, 3)), &range((3, 8), (3, 9))]);
+
+    assert_eq!(reply(&messages, 2).get("range"), Some(&range((0, 4), (0, 5))));
+    // `b` and `d` are lost to their errors
+    assert_eq!(reply(&messages, 3).as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn unknown_methods_and_bad_json() {
+    let messages = serve(&[
+        request(1, "textDocument/rename", Json::Null),
+        "Content-Length: 5\r\n\r\n{oops".to_string(),
+        request(2, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+
+    let error = messages[0].get("error").unwrap();
+    assert_eq!(error.get("code"), Some(&Json::from(-32601_i64)));
+    let error = messages[1].get("error").unwrap();
+    assert_eq!(error.get("code"), Some(&Json::from(-32700_i64)));
+    assert_eq!(messages[1].get("id"), Some(&Json::Null));
+}
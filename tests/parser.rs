@@ -0,0 +1,61 @@
+mod common;
+
+/// The syntax tree printed for `src`, or the error printed instead
+fn parse(src: &str) -> Result<String, String> {
+    let output = common::snd(&["--emit=ast", "-"], src);
+    match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap().trim_end().to_string()),
+        false => Err(String::from_utf8(output.stderr).unwrap()),
+    }
+}
+
+#[test]
+fn precedence_and_associativity() {
+    for (src, tree) in [
+        ("a || b || c", "(|| a (|| b c))"),
+        ("a && b && c", "(&& a (&& b c))"),
+        ("a || b && c", "(|| a (&& b c))"),
+        ("a && b || c", "(|| (&& a b) c)"),
+        ("a == b && c != d", "(&& (== a b) (!= c d))"),
+        ("a < b == c >= d", "(== (< a b) (>= c d))"),
+        ("a + b < c * d", "(< (+ a b) (* c d))"),
+        ("a - b - c", "(- (- a b) c)"),
+        ("a + b * c", "(+ a (* b c))"),
+        ("a * b + c", "(+ (* a b) c)"),
+        ("a / b % c * d", "(* (% (/ a b) c) d)"),
+        ("-a * b", "(* (- a) b)"),
+        ("!a && b", "(&& (! a) b)"),
+        ("(a + b) * c", "(* (+ a b) c)"),
+        ("f(a) + g(b)(c)", "(+ (f a) ((g b) c))"),
+    ] {
+        assert_eq!(parse(src).unwrap(), format!("(block {})", tree), "{}", src);
+    }
+}
+
+#[test]
+fn comparisons_do_not_chain() {
+    for src in ["a < b < c", "a == b == c", "a <= b > c", "a != b == c"] {
+        let err = parse(src).unwrap_err();
+        assert!(err.contains("cannot be chained"), "{}: {}", src, err);
+    }
+    assert!(parse("(a < b) < c").is_ok());
+}
+
+#[test]
+fn lines_at_the_items_column_start_items() {
+    for (src, tree) in [
+        ("let a = 1\n(a)", "(let a 1) a"),
+        ("let a = 1\n-a", "(let a 1) (- a)"),
+        ("f\n(a)\n(b)", "f a b"),
+        ("{\n  a\n  (b)\n  - c\n}", "(block a b (- c))"),
+        ("  a\n (b)", "a b"),
+        // further right, or in parentheses, they go on
+        ("let a = f\n  (b)\n  - c", "(let a (- (f b) c))"),
+        ("(a\n- b)", "(- a b)"),
+        ("f(a,\n(b))", "(f a b)"),
+        ("let a = 1\n+ 2", "(let a (+ 1 2))"),
+        ("a (b) - c", "(- (a b) c)"),
+    ] {
+        assert_eq!(parse(src).unwrap(), format!("(block {})", tree), "{}", src);
+    }
+}
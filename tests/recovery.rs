@@ -0,0 +1,123 @@
+use snd_language::ast::s0::Tree;
+use snd_language::lexer::Lexer;
+use snd_language::parser::{ParseError, Parser};
+use snd_language::{eval, fmt, source, vm};
+
+/// The partial tree of `src`, and each error's message with the text it
+/// points at
+fn parse(src: &str) -> (Tree, Vec<(String, String)>) {
+    let lexer = Lexer::from_source("<test>", src);
+    let file = lexer.file();
+    let (tree, errors) = Parser::new(lexer.lex().unwrap()).parse_partial();
+    let errors = errors
+        .iter()
+        .map(|err| {
+            let at = &src[err.context.start..err.context.start + err.context.len];
+            (err.to_string(), at.to_string())
+        })
+        .collect();
+    source::remove(file);
+    (tree, errors)
+}
+
+fn items(tree: &Tree) -> Vec<String> {
+    match tree {
+        Tree::Block { items, .. } => items.iter().map(Tree::to_string).collect(),
+        tree => vec![tree.to_string()],
+    }
+}
+
+fn errors(src: &str) -> Vec<(String, String)> {
+    parse(src).1
+}
+
+fn error(message: &str, at: &str) -> (String, String) {
+    (message.to_string(), at.to_string())
+}
+
+#[test]
+fn every_error_is_reported() {
+    let src = "let x = (1 + )\nlet y = 2 +\nlet z = f(1, , 3)\ndata T = | A\nlet w = 1 < 2 < 3\nw";
+    assert_eq!(
+        errors(src),
+        [
+            error("expected expression, found `)`", ")"),
+            error("expected expression, found keyword `let`", "let"),
+            error("expected expression, found `,`", ","),
+            error("expected identifier, found `|`", "|"),
+            error("comparison operator `<` cannot be chained", "<"),
+        ]
+    );
+}
+
+#[test]
+fn items_survive_errors() {
+    let (tree, errors) = parse("let x = (1 + )\nlet y = = 2\nlet z = f(1, , 3)\nz");
+    assert_eq!(errors.len(), 3);
+    assert_eq!(items(&tree), ["(let x (error))", "(error)", "(let z (f 1 3))", "z"]);
+}
+
+#[test]
+fn match_arms_recover() {
+    let src = "let f = fn(x) = match x\n  | 1 =>\n  | Some(1 +) => 2\n  | n => n\nf(1)";
+    let (tree, errors) = parse(src);
+    assert_eq!(
+        errors,
+        [
+            error("expected expression, found `|`", "|"),
+            error("expected `)`, found `+`", "+"),
+        ]
+    );
+    assert_eq!(items(&tree), ["(let f (fn (x) (match x (n n))))", "(f 1)"]);
+}
+
+#[test]
+fn blocks_recover() {
+    let (tree, errors) = parse("let f = fn(x) = { let y = ) }\nlet g = 1\n}\ng");
+    assert_eq!(
+        errors,
+        [
+            error("expected expression, found `)`", ")"),
+            error("expected expression, found `}`", "}"),
+        ]
+    );
+    assert_eq!(items(&tree), ["(let f (fn (x) (block (error))))", "(let g 1)", "(error)", "g"]);
+}
+
+#[test]
+fn parse_fails_with_every_error() {
+    let lexer = Lexer::from_source("<test>", "let x = )\nlet y = (");
+    let file = lexer.file();
+    let errors = Parser::new(lexer.lex().unwrap()).parse().unwrap_err();
+    let codes = errors
+        .iter()
+        .map(ParseError::diagnostic)
+        .map(|diag| diag.code)
+        .collect::<Vec<_>>();
+    assert_eq!(codes, [Some("E0100"), Some("E0100")]);
+    source::remove(file);
+}
+
+#[test]
+fn recovered_trees_format_and_run_up_to_their_errors() {
+    let src = "let x = 1\nlet y = (x + )\nlet z = x * 2\ny";
+    let lexer = Lexer::from_source("<test>", src);
+    let file = lexer.file();
+    let (tree, errors) = Parser::new(lexer.lex().unwrap()).parse_partial();
+    assert_eq!(errors.len(), 1);
+    let broken = |context: snd_language::context::Context| &src[context.start..context.start + context.len];
+
+    // what couldn't be parsed is printed as written
+    assert_eq!(fmt::format(&tree, &[]), format!("{}\n", src));
+
+    let err = eval::eval(&tree, &eval::Env::new()).unwrap_err();
+    assert_eq!(err.to_string(), "this part of the program has a syntax error");
+    assert_eq!(broken(err.context), "(x + )");
+
+    let mut vm = vm::Vm::new();
+    let program = vm.compile(&tree);
+    let err = vm.run(program).unwrap_err();
+    assert_eq!(err.to_string(), "this part of the program has a syntax error");
+    assert_eq!(broken(err.context), "(x + )");
+    source::remove(file);
+}
@@ -0,0 +1,64 @@
+use snd_language::context::Context;
+use snd_language::diagnostic::Diagnostic;
+use snd_language::source::{self, SourceMap};
+
+#[test]
+fn removed_slots_are_reused() {
+    let mut map = SourceMap::default();
+    let a = map.add("a", "1");
+    map.remove(a);
+    let b = map.add("b", "2");
+    assert_ne!(a, b);
+    assert_eq!(format!("{:?}", b), "FileId { index: 0, generation: 1 }");
+    assert_eq!(map.get(b).src, "2");
+
+    // not while something still reads the removed file
+    let held = map.get(b);
+    map.remove(b);
+    let c = map.add("c", "3");
+    assert_eq!(format!("{:?}", c), "FileId { index: 1, generation: 0 }");
+    assert_eq!(held.src, "2");
+
+    drop(held);
+    let d = map.add("d", "4");
+    assert_eq!(format!("{:?}", d), "FileId { index: 0, generation: 2 }");
+}
+
+#[test]
+#[should_panic(expected = "source file used after removal")]
+fn stale_ids_stay_stale() {
+    let mut map = SourceMap::default();
+    let a = map.add("a", "1");
+    map.remove(a);
+    map.add("b", "2");
+    map.get(a);
+}
+
+#[test]
+fn stale_ids_remove_nothing() {
+    let mut map = SourceMap::default();
+    let a = map.add("a", "1");
+    map.remove(a);
+    let b = map.add("b", "2");
+
+    // `a`'s slot is `b`'s now, a generation later
+    map.remove(a);
+    assert_eq!(map.get(b).src, "2");
+    let c = map.add("c", "3");
+    assert_eq!(format!("{:?}", c), "FileId { index: 1, generation: 0 }");
+}
+
+#[test]
+fn holds_keep_removed_files_readable() {
+    let file = source::add("held.snd", "kept");
+    let hold = source::hold(file).unwrap();
+    let diag = Diagnostic::error("held").with_label(Context { file, start: 0, len: 4 }, "");
+    source::remove(file);
+
+    assert_eq!(source::get(file).src, "kept");
+    drop(hold);
+    // the label holds the file too
+    assert!(diag.render(false).contains("1 | kept"), "{}", diag.render(false));
+    drop(diag);
+    assert!(source::hold(file).is_none());
+}
@@ -0,0 +1,43 @@
+use std::process::Output;
+
+mod common;
+
+fn run(program: &str) -> Output {
+    common::snd(&["-"], program)
+}
+
+#[test]
+fn million_iteration_loop() {
+    let output = run("
+        let count = fn(n, acc) = if n == 0 acc else count(n - 1, acc + 1)
+        count(1000000, 0)
+    ");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1000000\n");
+}
+
+#[test]
+fn tail_calls_from_match_arms() {
+    let output = run("
+        data List a = Cons(a, List a) | Nil
+        let build = fn(n, list) = match n
+            | 0 => list
+            | _ => build(n - 1, Cons(n, list))
+        let length = fn(list, acc) = match list
+            | Nil => acc
+            | Cons(_, rest) => length(rest, acc + 1)
+        length(build(1000000, Nil), 0)
+    ");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1000000\n");
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+    let output = run("
+        let deep = fn(n) = if n == 0 0 else 1 + deep(n - 1)
+        deep(2000000)
+    ");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stack overflow"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::snd;
+
+/// Each top-level `let` with its type, or the program's errors as JSON
+fn check(src: &str) -> Result<Vec<String>, String> {
+    let output = snd(&["--emit=types", "--error-format=json", "-"], src);
+    match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect()),
+        false => Err(String::from_utf8(output.stderr).unwrap()),
+    }
+}
+
+#[test]
+fn let_polymorphism() {
+    let src = "let id = fn(x) = x\nlet pair = fn(a, b) = id(a) == a && id(b) == b\npair(1, true)";
+    assert_eq!(check(src).unwrap(), ["id : fn('a) -> 'a", "pair : fn('a, 'b) -> Bool"]);
+
+    // parameters aren't generalized, so one can't be used at two types
+    let errors = check("let f = fn(g) = g(1) == g(true)").unwrap_err();
+    assert!(errors.contains(r#""code":"E0200""#), "{}", errors);
+}
+
+#[test]
+fn mismatch_diagnostic() {
+    let errors = check("let add = fn(a, b) = a + b\nadd(1, \"two\")").unwrap_err();
+    assert_eq!(errors.lines().count(), 1, "{}", errors);
+    for field in [
+        r#""code":"E0200""#,
+        r#""message":"mismatched types""#,
+        r#""byte_start":34,"byte_end":39"#,
+        r#""message":"expected Int, found String""#,
+    ] {
+        assert!(errors.contains(field), "{} in {}", field, errors);
+    }
+}
+
+#[test]
+fn data_types_are_declared_once() {
+    // `f` was checked against the first `T`, so it can't take a `C`
+    let src = "data T = A(Int) | B\nlet f = fn(t) = match t | B => 0 | A(x) => x\ndata T = A(Int) | B | C\nf(C)";
+    let errors = check(src).unwrap_err();
+    assert!(errors.contains(r#""code":"E0209""#), "{}", errors);
+    assert!(errors.contains(r#""message":"type `T` is already defined""#), "{}", errors);
+
+    let errors = check("data Int = Zero").unwrap_err();
+    assert!(errors.contains(r#""code":"E0209""#), "{}", errors);
+}
+
+#[test]
+fn constructors_are_declared_once() {
+    let errors = check("data T = A | B | A").unwrap_err();
+    assert!(errors.contains(r#""code":"E0210""#), "{}", errors);
+    assert!(errors.contains(r#""message":"constructor `A` is already defined""#), "{}", errors);
+    assert!(errors.contains(r#""byte_start":17,"byte_end":18"#), "{}", errors);
+
+    let errors = check("data T = A | B
+data U = B(Int)").unwrap_err();
+    assert!(errors.contains(r#""code":"E0210""#), "{}", errors);
+
+    // types and constructors are named apart
+    assert!(check("data T = T(Int)").is_ok());
+}